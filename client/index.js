@@ -38,6 +38,7 @@ document.getElementById("show-credits-btn").addEventListener("click", ev => Show
 document.getElementById("credits-done-btn").addEventListener("click", ev => ShowPanel("start-panel"));
 document.getElementById("host-cancel-btn").addEventListener("click", ev => QuitGame());
 document.getElementById("other-wait-cancel-btn").addEventListener("click", ev => QuitGame());
+document.getElementById("resign-btn").addEventListener("click", ev => TrySendMessage("resign", {}));
 
 document.getElementById("host-ready-btn").addEventListener("click", ev => {
     TrySendMessage("restart-ready", {});
@@ -149,11 +150,13 @@ function ResetGlobalState() {
         // Utility for server interaction
         websocketConnection: null,
         roomCode: undefined,
+        sessionToken: undefined, // Needed to reconnect
+        seat: 0, // Also needed to reconnect, 0 for the host
         pingLoopHandle: undefined,
         gameStarted: false,
         isHostPlayer: false,
         messagesToSend: [], // Message that are sent when not connected are stored here, to send them on reconnection
-        lastSentMessage: null, // Also to be resent if there is a problem
+        spectatorCount: 0,
         inReconnectionDelay: false,
     };
 
@@ -242,6 +245,7 @@ function OnGameStart() {
     PopulateKeyboard(letter => OnLetterTyped(letter), () => OnEnter(), () => OnBackspace(), currentOptions.language);
 
     document.getElementById("ingame-room-code").textContent = state.roomCode;
+    document.getElementById("resign-btn").classList.remove("hidden");
 
     ShowPanel("game-panel");
     HideChildren("game-hint-2")
@@ -254,6 +258,95 @@ function OnGameStart() {
     StartNextTurn();
 }
 
+// Rebuilds the game from the state sent by the server after a reconnection
+function OnStateResync(resync) {
+    state.seat = resync.seat;
+    currentOptions = resync.options;
+
+    if (!resync.game_started) { return; } // No game yet, the room panels are still right
+
+    if (state.waitPhaseInterfaceTimeout) {
+        clearTimeout(state.waitPhaseInterfaceTimeout)
+    }
+    ClearTimer();
+
+    PopulateWordGrids(WORD_LENGTH, MAX_WORD_COUNT, OnSabotageLetter);
+    ClearKeyboardHints();
+    ResetGlobalGameState();
+    PopulateKeyboard(letter => OnLetterTyped(letter), () => OnEnter(), () => OnBackspace(), currentOptions.language);
+
+    document.getElementById("ingame-room-code").textContent = state.roomCode;
+    ShowPanel("game-panel");
+    HideChildren("game-hint-2");
+    state.gameStarted = true;
+    state.currentTurn = resync.current_turn;
+
+    // Skipped turns aren't in the past words, so the words are put in the rows right before the current one
+    let isRestarting = resync.current_phase == "Restarting";
+    let lastRow = resync.current_phase == "Sabotaging" ? state.currentTurn : state.currentTurn - 1; // The words typed this turn are already in the past words while sabotaging
+    let firstRow = words => isRestarting ? 0 : Math.max(0, lastRow - words.length + 1);
+
+    let playerFirstRow = firstRow(resync.past_words);
+    resync.past_words.forEach((word, i) => {
+        SetWord(true, playerFirstRow + i, word);
+        if (i < resync.past_hints.length) {
+            let hints = resync.past_hints[i].map(txt => HintTextToId(txt));
+            SetHints(true, playerFirstRow + i, hints);
+            UpdateKnownHints(word, hints);
+        }
+    });
+    state.playerWords = resync.past_words.slice();
+
+    let other = resync.other_players.find(p => p.in_game) ?? resync.other_players[0];
+    if (other != null) {
+        let enemyFirstRow = firstRow(other.past_words);
+        other.past_words.forEach((word, i) => SetWord(false, enemyFirstRow + i, word));
+        state.enemyWords = other.past_words.slice();
+    }
+
+    if (isRestarting) {
+        SetBothGridActive();
+        OnGameEnd();
+        return;
+    }
+
+    document.getElementById("resign-btn").classList.toggle("hidden", !resync.in_game);
+
+    if (!resync.in_game) { // Out of this game, only watch it
+        StartSabotageWaitPhase();
+        return;
+    }
+
+    if (resync.current_phase == "Typing") {
+        if (resync.typed_word_this_turn != null) {
+            state.typedWord = resync.typed_word_this_turn;
+            SetWord(true, state.currentTurn, state.typedWord);
+            StartTypeWaitPhase();
+        }
+        else {
+            state.currentTurn -= 1; // StartNextTurn goes to the next turn
+            StartNextTurn();
+        }
+    }
+    else {
+        if (resync.attack_this_turn != null) {
+            SetHint(false, resync.attack_this_turn.id, state.currentTurn, HINT_RED);
+            StartSabotageWaitPhase();
+        }
+        else {
+            StartSabotagePhase();
+        }
+    }
+
+    if (other != null && other.done_this_phase) {
+        SetSubElement("game-hint-2", "game-hint-other-done");
+    }
+
+    if (resync.phase_deadline != null) {
+        StartPhaseTimer(resync.phase_deadline);
+    }
+}
+
 function StartNextTurn() {
     SetGameHint("game-hint-enter-word");
     ClearTimer();
@@ -363,6 +456,7 @@ function OnWordRejected() {
 
 function OnGameEnd() {
     ClearTimer();
+    document.getElementById("resign-btn").classList.add("hidden");
     SetSubElement("game-hint-2", "game-hint-restart");
     SetKeyboardEnterIcon("icon-restart");
     SetKeyboardBackspaceIcon("icon-restart-options");
@@ -379,7 +473,6 @@ function TrySendMessage(msgType, msgContent) {
     let msgText = JSON.stringify(msgObject);
 
     if (state.websocketConnection && state.websocketConnection.readyState == WebSocket.OPEN) {
-        if (msgType != "ping") { state.lastSentMessage = msgObject; }
         state.websocketConnection.send(msgText);
     }
     else {
@@ -409,7 +502,7 @@ function OnDisconnection() {
 
         Toast("room-reconnecting");
 
-        let connection = new WebSocket(GetApiUrl() + "reconnect/" + (state.isHostPlayer ? 0 : 1) + "/" + state.roomCode);
+        let connection = new WebSocket(GetApiUrl() + "reconnect/" + state.seat + "/" + state.roomCode + "?token=" + encodeURIComponent(state.sessionToken));
         state.websocketConnection = connection;
         
         connection.addEventListener("message", ev => HandleConnectionMessage(ev.data));
@@ -417,9 +510,7 @@ function OnDisconnection() {
             Toast("room-reconnected");
             StartPingLoop();
 
-            // Send messages that couldn't be sent during the disconnection
-            if (state.lastSentMessage != null) { TrySendMessage(state.lastSentMessage.type, state.lastSentMessage.content); }   
-            
+            // Send messages that couldn't be sent during the disconnection. The rest of the game is rebuilt from `state-resync`
             let msgToSendCopy = state.messagesToSend.slice();
             for (let msg of msgToSendCopy) {
                 console.log(msg);
@@ -462,27 +553,54 @@ function HandleConnectionMessage(msgText) {
         document.getElementById("host-waiting-other-hint").classList.add("hidden");
         ShowPanel("wait-panel");
     }
+    else if (msg.type == "session-token") {
+        state.sessionToken = msg.content;
+    }
+    else if (msg.type == "seat") {
+        state.seat = msg.content;
+    }
+    else if (msg.type == "state-resync") {
+        OnStateResync(msg.content);
+    }
     else if (msg.type == "other-player-connected") {
         //OnGameStart();
     }
+    else if (msg.type == "other-player-disconnected") {
+        Toast("toast-other-player-disconnected");
+    }
+    else if (msg.type == "spectator-count") {
+        if (msg.content > state.spectatorCount) {
+            Toast("toast-new-spectator");
+        }
+        state.spectatorCount = msg.content;
+    }
+    else if (msg.type == "other-player-left") {
+        Toast("toast-other-player-left");
+    }
+    else if (msg.type == "other-player-reconnected") {
+        Toast("toast-other-player-reconnected");
+    }
     else if (msg.type == "other-player-word") {
         if (state.waitPhaseInterfaceTimeout) {
             clearTimeout(state.waitPhaseInterfaceTimeout)
         }
 
-        SetWord(false, state.currentTurn, msg.content.word);
-
-        state.enemyWords.push(msg.content.word);
-        AutoScroll(false);
+        if (msg.content.word != null) { // null if the other player skipped their turn
+            SetWord(false, state.currentTurn, msg.content.word);
+            state.enemyWords.push(msg.content.word);
+            AutoScroll(false);
+        }
         
         if (state.currentTurn < MAX_WORD_COUNT - 1) { // If that was the last guess, no point in sabotaging
             StartSabotagePhase();
         }
     }
     else if (msg.type == "word-hints") {
-        let hints = msg.content.map(txt => HintTextToId(txt));
-        SetHints(true, state.currentTurn, hints);
-        UpdateKnownHints(state.typedWord, hints);
+        if (msg.content.length > 0) { // Empty if the player skipped their turn
+            let hints = msg.content.map(txt => HintTextToId(txt));
+            SetHints(true, state.currentTurn, hints);
+            UpdateKnownHints(state.typedWord, hints);
+        }
         StartNextTurn();
     }
     else if (msg.type == "word-rejected") {
@@ -492,12 +610,6 @@ function HandleConnectionMessage(msgText) {
 
         OnWordRejected();
     }
-    else if (msg.type == "solution") {
-        document.getElementById("solution-hint").textContent = msg.content;
-        SetGameHint("hint-loose-solution");
-        SetBothGridActive();
-        OnGameEnd();
-    }
     else if (msg.type == "restart") {
         document.getElementById("join-room-btn").classList.remove("connecting");
 
@@ -516,78 +628,85 @@ function HandleConnectionMessage(msgText) {
     }
     else if (msg.type == "other-player-is-done") {
         SetSubElement("game-hint-2", "game-hint-other-done");
+    }
+    else if (msg.type == "phase-deadline") {
+        StartPhaseTimer(msg.content.deadline);
+    }
+    else if (msg.type == "timer-expired") {
+        // The server skipped the word or attacked in place of the player
+        ClearTimer();
+        Toast("toast-too-late");
 
-        if (currentOptions.timer > 0) {
-            HideChildren("game-hint");
-            StartTimer(currentOptions.timer, () => {
-
-                if (state.currentPhase == PHASE_TYPE) {
-                    let rand = Math.random();
-
-                    let lateWords = {
-                        "English": ["AARGH", "OOMPH"],
-                        "French": ["MERDE", "FLUTE"],
-                    };
-
-                    let isCurrentWordOk = CheckIfWordIsValid(state.typedWord, currentOptions.language);
-
-                    if (!isCurrentWordOk) {
-                        let randId = Math.floor(rand * lateWords[currentOptions.language].length);
-                        let randWord = lateWords[currentOptions.language][randId];
-                        state.typedWord = randWord;
-
-                        for (let i = 0; i < WORD_LENGTH; i++) {
-                            SetLetter(true, i, state.currentTurn, state.typedWord[i]);
-                        }
-                    }
-                        
-                    InvalidAnimation(true, state.currentTurn);
-                    Toast("toast-too-late");
-
-                    OnEnter();
-                }
-                else if (state.currentPhase == PHASE_SABOTAGE) {
-                    let rand = Math.floor(Math.random() * WORD_LENGTH);
-                    OnSabotageLetter(rand, state.currentTurn);
-                }
-            });
+        if (state.currentPhase == PHASE_TYPE) {
+            for (let i = 0; i < state.typedWord.length; i++) {
+                RemoveLetter(true, i, state.currentTurn);
+            }
+            state.typedWord = "";
+            StartTypeWaitPhase();
+        }
+        else if (state.currentPhase == PHASE_SABOTAGE) {
+            StartSabotageWaitPhase();
         }
     }
     else if (msg.type == "game-options") {
         currentOptions = msg.content.options;
         console.log("Options received.")
     }
-    else if (msg.type == "other-player-win") {
+    else if (msg.type == "game-over") {
         if (state.waitPhaseInterfaceTimeout) {
             clearTimeout(state.waitPhaseInterfaceTimeout)
         }
 
-        SetWord(false, state.currentTurn, msg.content);
-        setTimeout(() => WinAnimation(false, state.currentTurn), MULTIPLE_LETTERS_ANIMATION_DELAY * WORD_LENGTH);
-        
-        state.enemyWords.push(msg.content);
+        let outcome = msg.content.outcome;
+        let solved = outcome == "win" || outcome == "loss" || outcome == "draw-by-both-solving";
 
-        AutoScroll(false);
-        SetBothGridActive();
-        SetGameHint("hint-loose");
-        OnGameEnd();
-    }
-    else if (msg.type == "you-win") {
-        if (state.waitPhaseInterfaceTimeout) {
-            clearTimeout(state.waitPhaseInterfaceTimeout)
+        // The words of the last turn are only sent with the end of the game when somebody found the solution
+        let otherWord = msg.content.words.find((word, seat) => seat != state.seat && word != null);
+        if (solved && otherWord != null) {
+            SetWord(false, state.currentTurn, otherWord);
+            state.enemyWords.push(otherWord);
+            AutoScroll(false);
         }
 
-        if (msg.content != null && msg.content != "") {
-            SetWord(false, state.currentTurn, msg.content);
-            state.playerWords.push(msg.content);
+        if (outcome == "win" || outcome == "draw-by-both-solving") {
+            WinAnimation(true, state.currentTurn);
+        }
+        if (outcome == "loss" || outcome == "draw-by-both-solving") {
+            setTimeout(() => WinAnimation(false, state.currentTurn), MULTIPLE_LETTERS_ANIMATION_DELAY * WORD_LENGTH);
+        }
+
+        if (outcome == "win") { SetGameHint("hint-win"); }
+        else if (outcome == "draw-by-both-solving") { SetGameHint("hint-both-win"); }
+        else if (outcome == "loss") { SetGameHint("hint-loose"); }
+        else if (outcome == "resignation" && msg.content.winners.includes(state.seat)) { SetGameHint("hint-win"); }
+        else {
+            document.getElementById("solution-hint").textContent = msg.content.solution;
+            SetGameHint("hint-loose-solution");
         }
 
-        AutoScroll(true);
         SetBothGridActive();
-        SetGameHint("hint-win");
-        WinAnimation(true, state.currentTurn);
         OnGameEnd();
     }
+    else if (msg.type == "you-forfeited") {
+        Toast("toast-you-forfeited");
+    }
+    else if (msg.type == "other-player-forfeited" || msg.type == "other-player-resigned") {
+        // Followed by game-over if the game can't go on without this player
+        Toast(msg.type == "other-player-forfeited" ? "toast-other-player-forfeited" : "toast-other-player-resigned");
+    }
+    else if (msg.type == "room-expired") {
+        // The room doesn't exist anymore, don't try to reconnect
+        let connection = state.websocketConnection;
+        state.websocketConnection = null;
+        connection.close();
+
+        Toast(msg.content.reason == "seat-abandoned" ? "toast-other-player-left" : "toast-room-expired");
+    }
+    else if (msg.type == "error") {
+        console.error("Message rejected by the server (" + msg.content.code + "): " + msg.content.message);
+        document.getElementById("toast-server-error-message").textContent = msg.content.message;
+        Toast("toast-server-error");
+    }
     else {
         console.error("Unknown message type: " + msg.type);
     }
@@ -734,6 +853,47 @@ function StartTimer(initialValue, onFinished) {
     }, initialValue * 1000)
 }
 
+// Starts the timer for the current phase, that ends at `deadline` (milliseconds since UNIX epoch)
+function StartPhaseTimer(deadline) {
+    if (state.currentPhase == PHASE_TYPE || state.currentPhase == PHASE_SABOTAGE) { // Only if the player still has to act
+        // The server resolves the phase at the deadline. Don't trust the local clock for more than the timer option
+        let secondsRemaining = Math.ceil((deadline - Date.now()) / 1000);
+        HideChildren("game-hint");
+        StartTimer(Math.max(0, Math.min(currentOptions.timer, secondsRemaining)), () => {
+
+            if (state.currentPhase == PHASE_TYPE) {
+                let rand = Math.random();
+
+                let lateWords = {
+                    "English": ["AARGH", "OOMPH"],
+                    "French": ["MERDE", "FLUTE"],
+                };
+
+                let isCurrentWordOk = CheckIfWordIsValid(state.typedWord, currentOptions.language);
+
+                if (!isCurrentWordOk) {
+                    let randId = Math.floor(rand * lateWords[currentOptions.language].length);
+                    let randWord = lateWords[currentOptions.language][randId];
+                    state.typedWord = randWord;
+
+                    for (let i = 0; i < WORD_LENGTH; i++) {
+                        SetLetter(true, i, state.currentTurn, state.typedWord[i]);
+                    }
+                }
+                    
+                InvalidAnimation(true, state.currentTurn);
+                Toast("toast-too-late");
+
+                OnEnter();
+            }
+            else if (state.currentPhase == PHASE_SABOTAGE) {
+                let rand = Math.floor(Math.random() * WORD_LENGTH);
+                OnSabotageLetter(rand, state.currentTurn);
+            }
+        });
+    }
+}
+
 function QuitGame()
 {
     if (state.websocketConnection != null) {
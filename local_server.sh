@@ -1,2 +1,2 @@
 cd server && 
-cargo r -- --localhost
\ No newline at end of file
+cargo r -- --words-dir ../words
\ No newline at end of file
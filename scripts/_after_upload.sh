@@ -16,7 +16,7 @@ while read branch || [[ -n $branch ]]; do
 Description=Wardle backend for version $branch (automatically generated)
 
 [Service]
-ExecStart=/opt/server/backend/$branch/server --port $port --localhost
+ExecStart=/opt/server/backend/$branch/server --port $port
 Restart=on-failure
 
 [Install]
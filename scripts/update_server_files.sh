@@ -25,6 +25,7 @@ while read branch || [[ -n $branch ]]; do
   cd ../server
   cargo build --release >/dev/null
   scp target/release/wardle-server root@\[2a09:6847:fa10:1410::278\]:/opt/server/backend/$branch/server >/dev/null
+  scp -r ../words root@\[2a09:6847:fa10:1410::278\]:/opt/server/backend/$branch/ >/dev/null
   cd ../scripts
 
   echo "Uploading files..."
@@ -0,0 +1,177 @@
+use crate::reaper::ReaperPolicy;
+
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Settings as given by the user. Each setting is read, by priority: from the command line,
+/// from its environment variable, from the config file, or else takes its default value.
+#[derive(clap::Parser, serde::Deserialize, Default)]
+#[command(about = "Backend server of Wardle")]
+#[serde(deny_unknown_fields)]
+struct Settings {
+    /// TOML file containing any of the settings below (with the names of the flags, in snake_case)
+    #[arg(long, env = "WARDLE_CONFIG")]
+    #[serde(skip)]
+    config: Option<PathBuf>,
+
+    /// Addresses to listen on, IPv4 or IPv6, separated by commas [default: 0.0.0.0]
+    #[arg(long, env = "WARDLE_BIND", value_delimiter = ',')]
+    bind: Option<Vec<IpAddr>>,
+
+    /// Port to listen on [default: 4268]
+    #[arg(long, env = "WARDLE_PORT")]
+    port: Option<u16>,
+
+    /// Directory containing `languages.json` and the word lists [default: `words` next to the executable]
+    #[arg(long, env = "WARDLE_WORDS_DIR")]
+    words_dir: Option<PathBuf>,
+
+    /// Where the statistics are saved [default: `statistics.json` next to the executable]
+    #[arg(long, env = "WARDLE_STATS_PATH")]
+    stats_path: Option<PathBuf>,
+
+    /// Token required in the `X-Admin-Token` header by the admin endpoints. They are disabled if not set
+    #[arg(long, env = "WARDLE_ADMIN_TOKEN", hide_env_values = true)]
+    admin_token: Option<String>,
+
+    /// off, error, warn, info, debug or trace [default: error]
+    #[arg(long, env = "WARDLE_LOG_LEVEL")]
+    log_level: Option<String>,
+
+    /// Maximum number of rooms at the same time, 0 for no limit [default: 0]
+    #[arg(long, env = "WARDLE_MAX_ROOMS")]
+    max_rooms: Option<usize>,
+
+    /// Maximum number of players the hosts can allow in their rooms, at least 2 [default: 8]
+    #[arg(long, env = "WARDLE_MAX_PLAYERS")]
+    max_players: Option<usize>,
+
+    /// Maximum number of spectators in one room, 0 to disable spectating [default: 10]
+    #[arg(long, env = "WARDLE_MAX_SPECTATORS")]
+    max_spectators: Option<usize>,
+
+    /// Seconds without receiving anything from a player before considering them disconnected [default: 10]
+    #[arg(long, env = "WARDLE_PLAYER_SILENCE_TIMEOUT")]
+    player_silence_timeout: Option<u64>,
+
+    /// Seconds a disconnected player has to come back before forfeiting the game, 0 to wait forever [default: 60]
+    #[arg(long, env = "WARDLE_RECONNECT_GRACE_PERIOD")]
+    reconnect_grace_period: Option<u64>,
+
+    /// Seconds before deleting a room where both players are disconnected, 0 to never delete it [default: 300]
+    #[arg(long, env = "WARDLE_EMPTY_ROOM_TIMEOUT")]
+    empty_room_timeout: Option<u64>,
+
+    /// Seconds before deleting a room nobody joined, 0 to never delete it [default: 3600]
+    #[arg(long, env = "WARDLE_LOBBY_TIMEOUT")]
+    lobby_timeout: Option<u64>,
+
+    /// Seconds before deleting a room where the only player still there waits for disconnected players, 0 to never delete it [default: 600]
+    #[arg(long, env = "WARDLE_ABANDONED_SEAT_TIMEOUT")]
+    abandoned_seat_timeout: Option<u64>,
+
+    /// Seconds before deleting a room, even if it is still used, 0 to never delete it [default: 86400]
+    #[arg(long, env = "WARDLE_MAX_ROOM_AGE")]
+    max_room_age: Option<u64>,
+}
+
+/// Configuration of the server, validated at startup
+pub struct Config {
+    pub bind: Vec<IpAddr>,
+    pub port: u16,
+    pub words_dir: PathBuf,
+    pub stats_path: PathBuf,
+    pub admin_token: Option<String>,
+    pub log_level: log::LevelFilter,
+    pub max_rooms: usize, // 0 for no limit
+    pub max_players: usize, // Per room, the hosts choose the actual number in the options
+    pub max_spectators: usize, // Per room
+    pub player_silence_timeout: u64, // Seconds
+    pub reconnect_grace_period: u64, // Seconds, 0 to wait forever
+    pub reaper: ReaperPolicy,
+}
+
+/// Reads the configuration. Must be called once at startup, before `get`.
+/// Exits the process with the help message if asked to.
+pub fn load() -> Result<&'static Config, String> {
+    let args = <Settings as clap::Parser>::parse();
+
+    let file = match &args.config {
+        Some(path) => {
+            let text = std::fs::read_to_string(path).map_err(|err| format!("Couldn't read {}: {}", path.display(), err))?;
+            toml::from_str::<Settings>(&text).map_err(|err| format!("Invalid config file {}: {}", path.display(), err))?
+        },
+        None => Settings::default(),
+    };
+
+    let config = merge(args, file)?;
+    validate(&config)?;
+
+    CONFIG.set(config).map_err(|_| String::from("The configuration was already loaded"))?;
+    Ok(get())
+}
+
+pub fn get() -> &'static Config {
+    CONFIG.get().expect("The configuration must be loaded at startup")
+}
+
+fn merge(args: Settings, file: Settings) -> Result<Config, String> {
+    let exe_dir = std::env::current_exe().map_err(|err| err.to_string())?
+        .parent().ok_or("couldn't get parent of executable")?
+        .to_path_buf();
+
+    let log_level = args.log_level.or(file.log_level).unwrap_or(String::from("error"));
+
+    Ok(Config {
+        bind: args.bind.or(file.bind).unwrap_or(vec![IpAddr::from([0, 0, 0, 0])]),
+        port: args.port.or(file.port).unwrap_or(4268),
+        words_dir: args.words_dir.or(file.words_dir).unwrap_or(exe_dir.join("words")),
+        stats_path: args.stats_path.or(file.stats_path).unwrap_or(exe_dir.join("statistics.json")),
+        admin_token: args.admin_token.or(file.admin_token).filter(|t| !t.is_empty()),
+        log_level: log_level.parse().map_err(|_| format!("Invalid log level: {}", log_level))?,
+        max_rooms: args.max_rooms.or(file.max_rooms).unwrap_or(0),
+        max_players: args.max_players.or(file.max_players).unwrap_or(8),
+        max_spectators: args.max_spectators.or(file.max_spectators).unwrap_or(10),
+        player_silence_timeout: args.player_silence_timeout.or(file.player_silence_timeout).unwrap_or(10),
+        reconnect_grace_period: args.reconnect_grace_period.or(file.reconnect_grace_period).unwrap_or(60),
+        reaper: ReaperPolicy {
+            empty_room_timeout: args.empty_room_timeout.or(file.empty_room_timeout).unwrap_or(300),
+            lobby_timeout: args.lobby_timeout.or(file.lobby_timeout).unwrap_or(3600),
+            abandoned_seat_timeout: args.abandoned_seat_timeout.or(file.abandoned_seat_timeout).unwrap_or(600),
+            max_room_age: args.max_room_age.or(file.max_room_age).unwrap_or(86400),
+        },
+    })
+}
+
+fn validate(config: &Config) -> Result<(), String> {
+    if config.bind.is_empty() {
+        return Err(String::from("At least one bind address is needed"));
+    }
+
+    if config.port == 0 {
+        return Err(String::from("The port can't be 0"));
+    }
+
+    if !config.words_dir.is_dir() {
+        return Err(format!("The words directory {} doesn't exist", config.words_dir.display()));
+    }
+
+    let stats_dir = config.stats_path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(std::path::Path::new("."));
+    if !stats_dir.is_dir() {
+        return Err(format!("The directory of the stats file {} doesn't exist", stats_dir.display()));
+    }
+
+    if config.max_players < 2 {
+        return Err(String::from("Rooms need at least 2 players"));
+    }
+
+    // Players must have the time to answer at least one ping of the server
+    if config.player_silence_timeout.saturating_mul(1000) <= crate::server_internal::SERVER_PING_INTERVAL {
+        return Err(format!("The player silence timeout must be longer than {} ms", crate::server_internal::SERVER_PING_INTERVAL));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,242 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use crate::Language;
+
+/// Words shorter or longer than this are refused when loading the lists
+pub const MIN_WORD_LENGTH: u64 = 3;
+pub const MAX_WORD_LENGTH: u64 = 10;
+
+/// Language used by the default options and for room codes
+pub const DEFAULT_LANGUAGE: &str = "English";
+
+/// File of the dictionary directory describing the languages
+const LANGUAGES_FILE: &str = "languages.json";
+
+/// Description of a language, as written in `languages.json`
+#[derive(serde::Deserialize, Clone)]
+struct LanguageInfo {
+    code: Language, // Identifier used in the options and the statistics
+    name: String, // Name displayed to the players
+    alphabet: String, // Lowercase letters that can be used in the words
+    #[serde(default = "HashMap::new")]
+    normalization: HashMap<char, char>, // Lowercase letters replaced by a letter of the alphabet (for instance 'é' by 'e')
+    accepted_words: String, // Path of the list of words that players can type, relative to the dictionary directory
+    secret_words: String, // Path of the list of words that can be chosen as the word to guess
+}
+
+/// Description of a language sent to the clients
+#[derive(serde::Serialize)]
+pub struct LanguageDescription {
+    code: Language,
+    name: String,
+    alphabet: String,
+    word_lengths: Vec<u64>, // Word lengths that can be played in this language
+}
+
+/// Word lists of a language for one word length
+struct WordLists {
+    secret_words: Vec<String>, // Words that can be chosen as the word to guess
+    accepted_words: HashSet<String>, // Words that players can type
+}
+
+struct LanguageDictionary {
+    info: LanguageInfo,
+    alphabet: HashSet<char>,
+    lists: HashMap<u64, WordLists>, // Lists for each word length
+}
+
+impl LanguageDictionary {
+    /// Converts a word to the form used in the game: letters are normalized and in uppercase.
+    /// None if the word contains letters that are not in the alphabet.
+    fn normalize_word(&self, word: &str) -> Option<String> {
+        word.chars().map(|c| {
+            let lower = single_char(c.to_lowercase()).unwrap_or(c);
+            let normalized = self.info.normalization.get(&lower).copied().unwrap_or(lower);
+
+            if !self.alphabet.contains(&normalized) {
+                return None;
+            }
+
+            // Letters without a single uppercase letter, like 'ß', are kept as they are
+            Some(single_char(normalized.to_uppercase()).unwrap_or(normalized))
+        }).collect()
+    }
+}
+
+/// Returns the only char of the iterator, None if there are zero or several chars
+fn single_char(mut chars: impl Iterator<Item = char>) -> Option<char> {
+    let c = chars.next()?;
+    if chars.next().is_some() { None } else { Some(c) }
+}
+
+/// All the languages and word lists loaded by the server
+pub struct Dictionaries {
+    directory: PathBuf, // Where the lists were loaded from, to be able to reload them
+    languages: Vec<LanguageDictionary>, // In the order of `languages.json`
+}
+
+pub type DictionariesHandle = Arc<RwLock<Dictionaries>>;
+
+impl Dictionaries {
+    /// Dictionaries without any word, used before the lists are loaded
+    pub fn empty() -> Dictionaries {
+        Dictionaries { directory: PathBuf::new(), languages: Vec::new() }
+    }
+
+    fn get_language(&self, lang: &Language) -> Option<&LanguageDictionary> {
+        self.languages.iter().find(|l| l.info.code == *lang)
+    }
+
+    fn get_lists(&self, lang: &Language, word_length: u64) -> Option<&WordLists> {
+        self.get_language(lang)?.lists.get(&word_length)
+    }
+
+    /// Converts a word typed by a player to the form used in the game (see `is_valid_word`).
+    /// None if the word contains letters that are not in the alphabet of the language.
+    pub fn normalize_word(&self, word: &str, lang: &Language) -> Option<String> {
+        self.get_language(lang)?.normalize_word(word)
+    }
+
+    /// Whether games can be played with this language and word length
+    pub fn has_word_lists(&self, lang: &Language, word_length: u64) -> bool {
+        self.get_lists(lang, word_length).is_some()
+    }
+
+    /// Returns a normalized word. None if there is no list for this length
+    pub fn get_random_secret_word(&self, lang: &Language, word_length: u64) -> Option<String> {
+        let lists = self.get_lists(lang, word_length)?;
+        let rand_id = rand::random_range(0..lists.secret_words.len());
+        Some(lists.secret_words[rand_id].clone())
+    }
+
+    /// `w` must be normalized with `normalize_word`
+    pub fn is_valid_word(&self, w: &str, lang: &Language, word_length: u64) -> bool {
+        match self.get_lists(lang, word_length) {
+            Some(lists) => lists.accepted_words.contains(w),
+            None => false,
+        }
+    }
+
+    pub fn get_languages_description(&self) -> Vec<LanguageDescription> {
+        self.languages.iter().map(|l| {
+            let mut word_lengths: Vec<u64> = l.lists.keys().copied().collect();
+            word_lengths.sort();
+
+            LanguageDescription {
+                code: l.info.code.clone(),
+                name: l.info.name.clone(),
+                alphabet: l.info.alphabet.clone(),
+                word_lengths,
+            }
+        }).collect()
+    }
+}
+
+/// Loads and validates the languages described in `languages.json` and their word lists.
+/// The list of accepted words must be sorted, and the list of secret words must only contain accepted words.
+/// Words are separated by `;`, a list can contain words of several lengths. 
+/// Words are stored normalized, so lists can contain letters that are normalized (like accents).
+pub fn load(directory: &Path) -> Result<Dictionaries, String> {
+    let languages_path = directory.join(LANGUAGES_FILE);
+    let languages_json = std::fs::read(&languages_path).map_err(|err| format!("Couldn't read {}: {}", languages_path.display(), err))?;
+    let infos = serde_json::from_slice::<Vec<LanguageInfo>>(&languages_json).map_err(|err| format!("{}: {}", languages_path.display(), err))?;
+
+    let mut languages: Vec<LanguageDictionary> = Vec::new();
+
+    for info in infos {
+        if languages.iter().any(|l| l.info.code == info.code) {
+            return Err(format!("{}: language {} is described twice", languages_path.display(), info.code.0));
+        }
+
+        let alphabet: HashSet<char> = info.alphabet.chars().collect();
+
+        if alphabet.is_empty() || alphabet.iter().any(|c| single_char(c.to_lowercase()) != Some(*c)) {
+            return Err(format!("{}: the alphabet of {} must only contain lowercase letters", languages_path.display(), info.code.0));
+        }
+
+        if let Some((from, to)) = info.normalization.iter().find(|(from, to)| alphabet.contains(from) || !alphabet.contains(to)) {
+            return Err(format!("{}: {} can't be normalized to {} for {}", languages_path.display(), from, to, info.code.0));
+        }
+
+        let accepted_path = directory.join(&info.accepted_words);
+        let secret_path = directory.join(&info.secret_words);
+
+        let accepted_words = read_word_list(&accepted_path)?;
+        let secret_words = read_word_list(&secret_path)?;
+
+        if let Some(pair) = accepted_words.windows(2).find(|pair| pair[0] >= pair[1]) {
+            return Err(format!("{}: list is not sorted or contains duplicates (\"{}\" before \"{}\")", accepted_path.display(), pair[0], pair[1]));
+        }
+
+        let accepted_set: HashSet<&String> = accepted_words.iter().collect();
+        if let Some(missing) = secret_words.iter().find(|w| !accepted_set.contains(w)) {
+            return Err(format!("{}: \"{}\" is not in the accepted words", secret_path.display(), missing));
+        }
+
+        let mut language = LanguageDictionary { info, alphabet, lists: HashMap::new() };
+
+        // Index the normalized words by length
+        for word in accepted_words {
+            let normalized = language.normalize_word(&word)
+                .ok_or_else(|| format!("{}: \"{}\" contains letters that are not in the alphabet", accepted_path.display(), word))?;
+
+            language.lists.entry(normalized.chars().count() as u64)
+                .or_insert_with(|| WordLists { secret_words: Vec::new(), accepted_words: HashSet::new() })
+                .accepted_words.insert(normalized);
+        }
+
+        let mut already_added = HashSet::new();
+        for word in secret_words {
+            let normalized = language.normalize_word(&word).unwrap(); // Already checked with the accepted words
+
+            if already_added.insert(normalized.clone()) { // Ignore duplicates so that all words have the same probability
+                language.lists.get_mut(&(normalized.chars().count() as u64)).unwrap().secret_words.push(normalized);
+            }
+        }
+
+        // Lengths without secret words can't be played
+        language.lists.retain(|_, l| !l.secret_words.is_empty());
+
+        languages.push(language);
+    }
+
+    let dictionaries = Dictionaries { directory: directory.to_path_buf(), languages };
+
+    // The default options and the room codes need this list
+    if !dictionaries.has_word_lists(&Language::default_language(), crate::game::DEFAULT_WORD_LENGTH) {
+        return Err(format!("No {} words of {} letters", DEFAULT_LANGUAGE, crate::game::DEFAULT_WORD_LENGTH));
+    }
+
+    Ok(dictionaries)
+}
+
+/// Loads the lists again from the same directory. If they are invalid, the current lists are kept.
+/// Rooms read the lists through the handle, so they use the new lists from their next game.
+pub fn reload(dictionaries: &DictionariesHandle) -> Result<(), String> {
+    let directory = dictionaries.read().map_err(|_| "Couldn't acquire dictionaries lock")?.directory.clone();
+    let new_dictionaries = load(&directory)?;
+
+    *dictionaries.write().map_err(|_| "Couldn't acquire dictionaries lock")? = new_dictionaries;
+    Ok(())
+}
+
+fn read_word_list(path: &Path) -> Result<Vec<String>, String> {
+    let content = std::fs::read_to_string(path).map_err(|err| format!("Couldn't read {}: {}", path.display(), err))?;
+
+    let words: Vec<String> = content.split(';')
+        .map(|w| w.trim())
+        .filter(|w| !w.is_empty())
+        .map(String::from)
+        .collect();
+
+    for word in words.iter() {
+        let length = word.chars().count() as u64;
+        if !(MIN_WORD_LENGTH..=MAX_WORD_LENGTH).contains(&length) {
+            return Err(format!("{}: \"{}\" must have between {} and {} letters", path.display(), word, MIN_WORD_LENGTH, MAX_WORD_LENGTH));
+        }
+    }
+
+    Ok(words)
+}
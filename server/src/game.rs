@@ -1,127 +1,209 @@
 
 use crate::{server_internal::send_message, *};
-
-type JsonMap = serde_json::Map<String, serde_json::Value>;
+use crate::protocol::{self, ClientMessage, ErrorCode, GameOutcome, ProtocolError, ServerMessage};
 
 pub const MESSAGE_TYPE_CURSORS: &str = "cursors";
 pub const MESSAGE_TYPE_NEW_PLAYER: &str = "new_player";
 pub const MESSAGE_TYPE_PLAYER_LIST: &str = "player_list";
 
-pub const WORD_LENGTH: u64 = 5;
-pub const MAX_WORD_COUNT: u64 = 6;
+pub const DEFAULT_WORD_LENGTH: u64 = 5;
+pub const DEFAULT_MAX_WORD_COUNT: u64 = 6;
+pub const DEFAULT_MAX_PLAYERS: usize = 2;
+
+/// Maximum number of guesses that can be set in the options
+pub const MAX_MAX_WORD_COUNT: u64 = 12;
+
+/// Longest timer that can be set in the options
+pub const MAX_TIMER: f32 = 600.0; // s
+
+/// Time added to the timer before resolving the phase automatically, to compensate for the network latency
+const TIMER_GRACE_PERIOD: u64 = 1000; // ms
 
 pub fn get_initial_game_state() -> GameState {
     GameState {
         current_turn: -1,
         current_phase: GamePhase::Typing,
         word_to_guess: None,
+        phase_deadline: None,
     }
 }
 
 pub fn game_start(room: &mut RoomState) {
     room.game_started = true;
-    room.do_for_all_players(&|p, _| p.ready_to_restart = false);
+    series::on_game_start(room);
+    for player in room.players.iter_mut() {
+        player.ready_to_restart = false;
+        player.in_game = !player.left; // Players who left are not waited for
+        player.past_words.clear();
+        player.past_hints.clear();
+        player.attacks_used.clear();
+    }
 
     // Pick a new word to guess
-    let word_to_guess = util::get_random_secret_word(room.game_options.language);
+    let word_to_guess = room.dictionaries.read().unwrap().get_random_secret_word(&room.game_options.language, room.game_options.word_length);
+    let word_to_guess = match word_to_guess {
+        Some(w) => w,
+        None => { // The lists were reloaded without the list for these options, go back to the default options
+            log::error!("No word list for the options of room {}, default options will be used", room.join_code);
+            room.game_options = GameOptions::default();
+            send_options(room.game_options.clone(), room.get_player(HOST_SEAT));
+            room.dictionaries.read().unwrap().get_random_secret_word(&room.game_options.language, room.game_options.word_length).unwrap()
+        },
+    };
     println!("Word to guess is {}", word_to_guess);
     room.game_state.word_to_guess = Some(word_to_guess);
 
-    send_options(room.game_options.clone(), room.get_player(false));
+    for seat in 0..room.players.len() {
+        if seat != HOST_SEAT {
+            send_options(room.game_options.clone(), room.get_player(seat));
+        }
+    }
+
+    teams::assign_teams(room);
     start_turn(room);
 }
 
 pub fn start_turn(room: &mut RoomState) {
     room.game_state.current_turn += 1;
     room.game_state.current_phase = GamePhase::Typing;
-    room.host_player.typed_word_this_turn = None;
-    room.other_player.as_mut().unwrap().typed_word_this_turn = None;
-    room.host_player.letter_sabotaged_this_turn = None;
-    room.other_player.as_mut().unwrap().letter_sabotaged_this_turn = None;
+    room.game_state.phase_deadline = None;
+    for player in room.players.iter_mut() {
+        player.typed_word_this_turn = None;
+        player.turn_skipped = false;
+        player.attack_this_turn = None;
+    }
+
+    if room.game_options.teams {
+        teams::send_team_roles(room);
+    }
+}
+
+/// Seats of the players who play the current game
+pub fn get_seats_in_game(room: &RoomState) -> Vec<usize> {
+    (0..room.players.len()).filter(|seat| room.players[*seat].in_game).collect()
 }
 
-/// Do things if both player typed their word. Returns wether both player typed their words (returns true is there is a victory)
+/// Do things if all the players typed their word. Returns wether all the players typed their words (returns true is there is a victory)
 pub fn check_for_type_end(room: &mut RoomState) -> bool {
-    // Check for victory
-    let player_a = &mut room.host_player;
-    let player_b = room.other_player.as_mut().unwrap();
-    let word_to_guess = &room.game_state.word_to_guess.as_ref().unwrap();
+    // Before handling turn end, check that all the players entered a word (or ran out of time)
+    if !(0..room.players.len()).all(|seat| has_typed_or_skipped(room, seat)) {
+        return false;
+    }
 
-    let a_wins = handle_victory_condition(player_a, player_b, word_to_guess);
-    let b_wins = handle_victory_condition(player_b, player_a, word_to_guess);
+    // Check for victory once the turn is over, so that several players can find the word in the same turn
+    let word_to_guess = room.game_state.word_to_guess.clone().unwrap();
+    let finders: Vec<usize> = get_seats_in_game(room).into_iter()
+        .filter(|seat| room.players[*seat].typed_word_this_turn.as_ref() == Some(&word_to_guess))
+        .collect();
 
-    if a_wins || b_wins { // Game finished 
-        on_game_end(room, false);
+    if !finders.is_empty() { // Game finished
+        handle_victory(room, &finders);
         return true;
-    } 
-
-    // Before handling turn end, check that both plater entered a word
-    if room.get_player(false).typed_word_this_turn.is_none() 
-    || room.get_player(true).typed_word_this_turn.is_none() {
-        return false;
     }
 
     // Go to next phase
     room.game_state.current_phase = GamePhase::Sabotaging;
+    room.game_state.phase_deadline = None;
+
+    // Send the words to the other players, teammates included
+    let seats = get_seats_in_game(room);
+    let typists: Vec<usize> = seats.iter().copied().filter(|seat| teams::is_typist(room, *seat)).collect();
+    for &seat in &typists {
+        let word = room.players[seat].typed_word_this_turn.clone();
+        for &other in seats.iter().filter(|other| **other != seat) {
+            send_message(room.get_player(other), ServerMessage::OtherPlayerWord { seat, word: word.clone() });
+        }
+    }
+    spectators::send_spectator_words(room);
 
-    // Send other words
-    #[derive(serde::Serialize)]
-    struct Msg<'a> {
-        word: &'a str,
+    let was_last_guess = room.game_state.current_turn == room.game_options.max_word_count as i64 - 1; // Was this the last possible guess for this game?
+
+    for &seat in &typists {
+        if let Some(word) = room.players[seat].typed_word_this_turn.clone() {
+            for member in teams::get_board_members(room, seat) {
+                room.get_player(member).past_words.push(word.clone());
+            }
+        }
     }
 
-    let host_word = room.host_player.typed_word_this_turn.clone().unwrap();
-    let other_word = room.other_player.as_ref().unwrap().typed_word_this_turn.clone().unwrap();
-    let host_msg = Msg {
-        word: &other_word,
-    };
-    let other_msg = Msg {
-        word: &host_word,
-    };
+    if was_last_guess {
+        on_game_end(room, GameEnd::DrawByExhaustion, &[], None);
+    }
+
+    return true;
+}
 
-    send_message(&mut room.host_player, "other-player-word", &host_msg);
-    send_message(room.other_player.as_mut().unwrap(), "other-player-word", &other_msg);
+/// Players who don't play the current game, or who don't type this turn, have nothing to type
+fn has_typed_or_skipped(room: &RoomState, seat: usize) -> bool {
+    let player = &room.players[seat];
+    !teams::is_typist(room, seat) || player.typed_word_this_turn.is_some() || player.turn_skipped
+}
 
-    let was_last_guess = room.game_state.current_turn == MAX_WORD_COUNT as i64 - 1; // Was this the last possible guess for this game?
-    let solution = room.game_state.word_to_guess.clone();
+fn has_attacked(room: &RoomState, seat: usize) -> bool {
+    !teams::is_captain(room, seat) || room.players[seat].attack_this_turn.is_some()
+}
 
-    room.do_for_all_players(&|player, _| {
-        player.past_words.push(player.typed_word_this_turn.as_ref().unwrap().clone());
+/// The teammates of the players who found the word win with them. If several sides found it in the same turn, it's a draw between them
+fn handle_victory(room: &mut RoomState, finders: &[usize]) {
+    let winners: Vec<usize> = get_seats_in_game(room).into_iter()
+        .filter(|seat| finders.iter().any(|finder| teams::are_teammates(room, *seat, *finder)))
+        .collect();
+    let winning_sides = finders.iter()
+        .filter(|finder| finders.iter().find(|other| teams::are_teammates(room, **finder, **other)) == Some(*finder))
+        .count();
 
-        if was_last_guess {
-            send_message(player, "solution", &solution);
-        }
-    });
+    spectators::send_spectator_words(room);
 
-    if was_last_guess { 
-        on_game_end(room, true);
-    }
+    let game_end = if winning_sides > 1 { GameEnd::DrawByBothSolving } else { GameEnd::Win };
+    on_game_end(room, game_end, &winners, None);
+}
 
-    return true;
+/// How a game ended, counted in the statistics
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum GameEnd {
+    Win, // One side found the word
+    DrawByBothSolving, // Several sides found the word in the same turn
+    DrawByExhaustion, // Nobody found the word before the last turn
+    Resignation, // Only one side is left, the last one who left resigned
+    Abandonment, // Only one side is left, the last one who left didn't come back before the end of the grace period
 }
 
-// Handles victory. Returns wether `player` won the game
-pub fn handle_victory_condition(player: &mut Player, other: &mut Player, word_to_guess: &str) -> bool {
-    match &player.typed_word_this_turn {
-        Some(w) => {
-            if *w == word_to_guess {
-                match &other.typed_word_this_turn {
-                    Some(other_w) => send_message(player, "you-win", &other_w),
-                    None => send_message(player, "you-win", &()),
-                }
-                
-                send_message(other, "other-player-win", &word_to_guess);
-                true
-            }
-            else {
-                false
-            }
-        },
-        None => false,
+/// Result of the game from the point of view of the player
+fn get_outcome(game_end: GameEnd, winners: &[usize], seat: usize) -> GameOutcome {
+    match game_end {
+        GameEnd::Win if winners.contains(&seat) => GameOutcome::Win,
+        GameEnd::DrawByBothSolving if winners.contains(&seat) => GameOutcome::DrawByBothSolving,
+        GameEnd::Win | GameEnd::DrawByBothSolving => GameOutcome::Loss,
+        GameEnd::DrawByExhaustion => GameOutcome::DrawByExhaustion,
+        GameEnd::Resignation => GameOutcome::Resignation,
+        GameEnd::Abandonment => GameOutcome::Abandonment,
     }
 }
 
-pub fn on_game_end(room: &mut RoomState, is_a_draw: bool) {
+/// Tells everybody how the game ended, with `game-over`. `winners` are the seats of the players who won (or who found the word
+/// in a draw by both solving), empty if nobody won. `player` is the player whose resignation or abandonment ended the game
+pub fn on_game_end(room: &mut RoomState, game_end: GameEnd, winners: &[usize], player: Option<usize>) {
+    let solution = room.game_state.word_to_guess.clone().unwrap_or_default();
+    let words: Vec<Option<String>> = match game_end {
+        GameEnd::Resignation | GameEnd::Abandonment => vec![None; room.players.len()], // The turn wasn't finished, the words stay secret
+        _ => room.players.iter().map(|p| p.typed_word_this_turn.clone()).collect(),
+    };
+
+    for seat in 0..room.players.len() {
+        let game_over = protocol::GameOver {
+            outcome: get_outcome(game_end, winners, seat),
+            winners: winners.to_vec(),
+            player,
+            solution: solution.clone(),
+            words: words.clone(),
+        };
+        send_message(room.get_player(seat), ServerMessage::GameOver(game_over));
+    }
+    spectators::send_spectator_game_end(room, game_end, winners.to_vec(), player);
+
+    let end_turn = room.game_state.current_turn; // Kept for the statistics, the game state is reset below
+
     // Just reset the room for it to be ready for restart
     room.game_state = get_initial_game_state();
     room.game_state.current_phase = GamePhase::Restarting;
@@ -129,123 +211,682 @@ pub fn on_game_end(room: &mut RoomState, is_a_draw: bool) {
     room.game_count += 1;
 
     statistics::update_stats(&room.statistics, &|stats| {
-        if is_a_draw {
-            stats.total_draws += 1;
-        }
-        else {
-            stats.total_wins += 1;
+        statistics::increment_stat_map_counter(&mut stats.game_ends, game_end);
+        match game_end {
+            GameEnd::Win => stats.total_wins += 1,
+            GameEnd::DrawByBothSolving | GameEnd::DrawByExhaustion => stats.total_draws += 1,
+            GameEnd::Resignation => return, // The game wasn't finished, it doesn't count in the other stats
+            GameEnd::Abandonment => {
+                stats.abandoned_games += 1;
+                return;
+            },
         }
-        statistics::increment_stat_map_counter(&mut stats.language, room.game_options.language);
+        statistics::increment_stat_map_counter(&mut stats.language, room.game_options.language.clone());
         statistics::increment_stat_map_counter(&mut stats.timer, room.game_options.timer as u64);
         statistics::increment_stat_map_counter(&mut stats.game_count_for_one_room, room.game_count);
-
-        log::info!("{}", room.game_state.current_turn);
-        statistics::increment_stat_map_counter(&mut stats.win_turn, room.game_state.current_turn.unsigned_abs());
+        statistics::increment_stat_map_counter(&mut stats.win_turn, end_turn.max(0) as u64);
     });
+
+    // Draws don't count in series, nor games won because the other players didn't come back
+    let series_winners = match game_end {
+        GameEnd::Win | GameEnd::Resignation => winners,
+        _ => &[],
+    };
+    series::on_game_end(room, series_winners);
 }
 
 pub fn check_for_sabotage_end(room: &mut RoomState) -> bool {
-    // Check that both player sabotaged
-    if room.get_player(false).letter_sabotaged_this_turn.is_none() 
-    || room.get_player(true).letter_sabotaged_this_turn.is_none() {
+    // Check that all the players attacked
+    if !(0..room.players.len()).all(|seat| has_attacked(room, seat)) {
         return false;
     }
 
-    // Send hints
-    let word_to_guess = room.game_state.word_to_guess.clone();
-    room.do_for_all_players(&|player: &mut Player, other| {
-        let hints = hints::get_hints(
-            word_to_guess.as_ref().unwrap(), 
-            &player.typed_word_this_turn.as_ref().unwrap(), 
-            other.letter_sabotaged_this_turn.unwrap() as usize
-        );
+    // Send hints. The hints of each player depend on the attacks that target them
+    let word_to_guess = room.game_state.word_to_guess.clone().unwrap();
+    let seats = get_seats_in_game(room);
+    let typists: Vec<usize> = seats.iter().copied().filter(|seat| teams::is_typist(room, *seat)).collect();
+
+    for &seat in &typists {
+        let attacks: Vec<PlayerAttack> = seats.iter()
+            .filter_map(|attacker| room.players[*attacker].attack_this_turn)
+            .filter(|attack| attack.target == seat)
+            .collect();
+
+        let hidden_letters: Vec<(usize, HintType)> = attacks.iter().filter_map(|attack| match attack.attack {
+            Attack::Sabotage => Some((attack.letter_id as usize, HintType::Red)),
+            Attack::InvisibleSabotage => Some((attack.letter_id as usize, HintType::None)),
+            Attack::Espionage => None,
+        }).collect();
+
+        let hints = match &room.players[seat].typed_word_this_turn {
+            Some(word) => hints::get_hints(&word_to_guess, word, &hidden_letters),
+            None => Vec::new(), // The player skipped their turn, nothing to show
+        };
+
+        for &spy in &seats {
+            match room.players[spy].attack_this_turn {
+                Some(attack) if attack.attack == Attack::Espionage && attack.target == seat => {
+                    let hint = hints.get(attack.letter_id as usize).copied();
+                    send_message(room.get_player(spy), ServerMessage::EspionageResult { seat, id: attack.letter_id, hint });
+                },
+                _ => {},
+            }
+        }
 
-        send_message(player, "word-hints", &hints::get_hints_strings(hints));    
-    });
+        // Teammates share the board
+        for member in teams::get_board_members(room, seat) {
+            let player = room.get_player(member);
+            if !hints.is_empty() {
+                player.past_hints.push(hints.clone());
+            }
+            send_message(player, ServerMessage::WordHints(hints.clone()));
+        }
+    }
 
+    spectators::send_spectator_hints(room);
     start_turn(room); // Next turn
 
     return true;
 }
 
+/// Starts the timer for the current phase, if enabled in the options. 
+/// As described in the options, the timer starts when the first player has done their action.
+pub fn start_phase_timer(room: &mut RoomState) {
+    if room.game_options.timer <= 0.0 || room.game_state.phase_deadline.is_some() {
+        return;
+    }
+
+    let Ok(duration) = std::time::Duration::try_from_secs_f32(room.game_options.timer) else {
+        log::error!("Invalid timer {} in room {}", room.game_options.timer, room.join_code);
+        return;
+    };
+    let deadline = std::time::Instant::now() + duration + std::time::Duration::from_millis(TIMER_GRACE_PERIOD);
+    room.game_state.phase_deadline = Some(deadline);
+
+    let msg = ServerMessage::PhaseDeadline {
+        deadline: get_deadline_timestamp(deadline),
+        turn: room.game_state.current_turn,
+        phase: room.game_state.current_phase,
+    };
+    server_internal::send_message_to_all_players(room, msg);
+}
+
+/// If the deadline of the current phase is passed, do the missing actions in place of the players
+pub fn check_for_phase_timeout(room: &mut RoomState) {
+    match room.game_state.phase_deadline {
+        Some(deadline) if std::time::Instant::now() >= deadline => {},
+        _ => return,
+    }
+
+    room.game_state.phase_deadline = None;
+
+    match room.game_state.current_phase {
+        GamePhase::Typing => {
+            for seat in 0..room.players.len() {
+                if !has_typed_or_skipped(room, seat) {
+                    let player = room.get_player(seat);
+                    player.turn_skipped = true;
+                    send_message(player, ServerMessage::TimerExpired);
+                }
+            }
+
+            check_for_type_end(room);
+        },
+        GamePhase::Sabotaging => {
+            let options = room.game_options.clone();
+
+            for seat in 0..room.players.len() {
+                if has_attacked(room, seat) {
+                    continue;
+                }
+
+                let targets = teams::get_opponents(room, seat);
+                let player = room.get_player(seat);
+
+                // Sabotage a random letter of a random opponent, as the client does when its timer ends.
+                // If the player can't sabotage anymore, pick a random attack among the ones they can still use
+                let attack = if can_use_attack(&options, player, Attack::Sabotage) {
+                    Attack::Sabotage
+                }
+                else {
+                    let attacks: Vec<Attack> = [Attack::InvisibleSabotage, Attack::Espionage].into_iter()
+                        .filter(|a| can_use_attack(&options, player, *a))
+                        .collect();
+                    attacks[rand::random_range(0..attacks.len())]
+                };
+
+                let target = targets[rand::random_range(0..targets.len())];
+
+                register_attack(player, PlayerAttack { attack, letter_id: rand::random_range(0..options.word_length), target });
+                send_message(player, ServerMessage::TimerExpired);
+            }
+
+            check_for_sabotage_end(room);
+        },
+        GamePhase::Restarting => {},
+    }
+}
+
+/// Whether a game is being played (not waiting for the players to restart)
+pub fn is_game_in_progress(room: &RoomState) -> bool {
+    room.game_started && room.game_state.current_phase != GamePhase::Restarting
+}
+
+/// When the player will forfeit if they don't come back. None if they are connected, or if they can't forfeit
+pub fn get_forfeit_deadline(room: &RoomState, seat: usize) -> Option<std::time::Instant> {
+    let player = &room.players[seat];
+
+    if !is_game_in_progress(room) || !player.in_game {
+        return None;
+    }
+
+    let grace_period = std::time::Duration::from_secs(room.reconnect_grace_period);
+    let disconnected_since = player.disconnected_since?;
+
+    if player.left {
+        return Some(disconnected_since); // They won't come back, no need to wait
+    }
+
+    if grace_period.is_zero() {
+        return None; // Wait forever
+    }
+
+    Some(disconnected_since + grace_period)
+}
+
+/// Removes from the game the disconnected players who didn't come back before the end of the grace period.
+/// The game ends if only one player (or one team) is left
+pub fn check_for_forfeit(room: &mut RoomState) {
+    for seat in 0..room.players.len() {
+        match get_forfeit_deadline(room, seat) {
+            Some(deadline) if std::time::Instant::now() >= deadline => {},
+            _ => continue,
+        }
+
+        println!("A player forfeited in room {}", room.join_code);
+
+        send_message(room.get_player(seat), ServerMessage::YouForfeited); // Received if they come back
+        for other in (0..room.players.len()).filter(|other| *other != seat) {
+            send_message(room.get_player(other), ServerMessage::OtherPlayerForfeited { seat });
+        }
+
+        remove_from_game(room, &[seat], seat, GameEnd::Abandonment);
+
+        if !is_game_in_progress(room) {
+            return;
+        }
+    }
+}
+
+/// The player gives up the current game, with their team in team mode
+pub fn resign(room: &mut RoomState, seat: usize) {
+    println!("A player resigned in room {}", room.join_code);
+
+    for other in (0..room.players.len()).filter(|other| *other != seat) {
+        send_message(room.get_player(other), ServerMessage::OtherPlayerResigned { seat });
+    }
+
+    let members = teams::get_board_members(room, seat);
+    remove_from_game(room, &members, seat, GameEnd::Resignation);
+}
+
+/// Takes players out of the current game because of `player`. The game ends if only one side is left:
+/// it wins if the others resigned, nobody wins if they abandoned
+fn remove_from_game(room: &mut RoomState, seats: &[usize], player: usize, reason: GameEnd) {
+    for &seat in seats {
+        room.get_player(seat).in_game = false;
+    }
+
+    if teams::get_side_count(room) < 2 {
+        let winners = match reason {
+            GameEnd::Resignation => get_seats_in_game(room),
+            _ => Vec::new(),
+        };
+        on_game_end(room, reason, &winners, Some(player));
+        return;
+    }
+
+    spectators::send_spectator_state(room);
+
+    if room.game_options.teams { // The roles in the team of the player may change
+        teams::send_team_roles(room);
+    }
+
+    // The other players may have been waiting for this one
+    match room.game_state.current_phase {
+        GamePhase::Typing => { check_for_type_end(room); },
+        GamePhase::Sabotaging => { check_for_sabotage_end(room); },
+        GamePhase::Restarting => {},
+    }
+}
+
+/// Checks that the attack is enabled in the options and that the player didn't use all of them in this game
+pub fn can_use_attack(options: &GameOptions, player: &Player, attack: Attack) -> bool {
+    let used = player.attacks_used.get(&attack).copied().unwrap_or(0);
+    options.is_attack_available(attack) && options.get_attack_budget(attack).is_none_or(|budget| used < budget)
+}
+
+fn register_attack(player: &mut Player, attack: PlayerAttack) {
+    player.attack_this_turn = Some(attack);
+    *player.attacks_used.entry(attack.attack).or_insert(0) += 1;
+}
+
+/// Starts a game once the room is full and all the players are ready. Players who left are not waited for
 pub fn check_for_restart_end(room: &mut RoomState) {
-    // Check that both player restarted
-    if !room.player_exists(false) || !room.get_player(false).ready_to_restart 
-    || !room.get_player(true).ready_to_restart {
+    let players: Vec<&Player> = room.players.iter().filter(|p| !p.left).collect();
+
+    if players.len() < 2 || players.len() < room.game_options.max_players
+    || !players.iter().all(|p| p.ready_to_restart) {
+        return;
+    }
+
+    if room.game_options.teams && !teams::are_teams_full(players.len()) {
         return;
     }
 
     game_start(room); // Do game start setup before sending restart message to make sure the new options arrive first
     
-    room.do_for_all_players(&|p, _| { send_message(p, "restart", &()); });
+    server_internal::send_message_to_all_players(room, ServerMessage::Restart);
+    spectators::send_spectator_state(room);
 }
 
-pub fn handle_one_message(room: &mut RoomState, msg_type: &str, msg_contents: &JsonMap, is_host: bool) -> Result<(), String> {    
-    match msg_type {
-        "ping" => {
+pub fn handle_one_message(room: &mut RoomState, message: ClientMessage, seat: usize) -> Result<(), ProtocolError> {    
+    match message {
+        ClientMessage::Ping {} => {
             // Nothing to do
         },
-        "word" => {
+        ClientMessage::Word { word: raw_word } => {
             if room.game_state.current_phase == GamePhase::Restarting { // Word received too late!
                 return Ok(());
             }
 
-            if room.game_state.current_phase != GamePhase::Typing { return Err(String::from("Word received during wrong phase")); }
-
-            let word = crate::util::get_json_str(msg_contents, "word").unwrap();
-
-            if util::is_valid_word(word, room.game_options.language) {   
-                room.get_player(is_host).typed_word_this_turn = Some(String::from(word));
-                let ended = check_for_type_end(room);
+            if room.game_state.current_phase != GamePhase::Typing { return Err(ProtocolError::new(ErrorCode::WrongPhase, "Word received during wrong phase")); }
+            if !room.get_player(seat).in_game { return Err(ProtocolError::new(ErrorCode::WrongPhase, "You don't play this game")); }
+            if !teams::is_typist(room, seat) { return Err(ProtocolError::new(ErrorCode::NotYourTurn, "Your teammate types this turn")); }
+            if has_typed_or_skipped(room, seat) { return Err(ProtocolError::new(ErrorCode::ActionAlreadyDone, "Word already received for this turn")); }
+
+            let word = {
+                let dictionaries = room.dictionaries.read().unwrap();
+                dictionaries.normalize_word(&raw_word, &room.game_options.language)
+                    .filter(|w| dictionaries.is_valid_word(w, &room.game_options.language, room.game_options.word_length))
+            };
+
+            let word = match word {
+                Some(w) => w,
+                None => {
+                    send_message(room.get_player(seat), ServerMessage::WordRejected);
+                    return Ok(());
+                }
+            };
 
-                if !ended { // Tell the other player
-                    send_message(room.get_player(!is_host), "other-player-is-done", &());
+            if room.game_options.mode == Mode::Hard {
+                let player = room.get_player(seat);
+                if let Err(violation) = hints::check_hard_mode(&word, &player.past_words, &player.past_hints) {
+                    send_message(player, ServerMessage::WordRejectedHardMode(violation));
+                    return Ok(());
                 }
             }
-            else {
-                send_message(room.get_player(is_host), "word-rejected", &());
+
+            room.get_player(seat).typed_word_this_turn = Some(word);
+            let ended = check_for_type_end(room);
+
+            if !ended { // Tell the other players
+                send_done_to_other_players(room, seat);
+                start_phase_timer(room);
             }
         },
-        "sabotage" => {
-            if room.game_state.current_phase != GamePhase::Sabotaging { return Err(String::from("Sabotage received during wrong phase")); }
-
-            let id = crate::util::get_json_number(msg_contents, "id").unwrap();
-            room.get_player(is_host).letter_sabotaged_this_turn = Some(id.as_u64().unwrap()); // TODO: check that the number is between 0 and 5
-            let ended = check_for_sabotage_end(room);
+        ClientMessage::Sabotage { id, target } => {
+            handle_attack(room, Attack::Sabotage, id, target, seat)?;
+        },
+        ClientMessage::Attack { attack, id, target } => {
+            handle_attack(room, attack, id, target, seat)?;
+        },
+        ClientMessage::Resign {} => {
+            if !is_game_in_progress(room) { return Err(ProtocolError::new(ErrorCode::WrongPhase, "There is no game to resign from")); }
+            if !room.get_player(seat).in_game { return Err(ProtocolError::new(ErrorCode::WrongPhase, "You don't play this game")); }
 
-            // Tell the other player
-            if !ended { send_message(room.get_player(!is_host), "other-player-is-done", &()); }
+            resign(room, seat);
         },
-        "restart-ready" => {
-            if room.game_state.current_phase != GamePhase::Restarting && room.game_started { return Err(String::from("Restart request received during wrong phase")); }
-            room.get_player(is_host).ready_to_restart = true;
+        ClientMessage::RestartReady {} => {
+            if room.game_state.current_phase != GamePhase::Restarting && room.game_started { return Err(ProtocolError::new(ErrorCode::WrongPhase, "Restart request received during wrong phase")); }
+            room.get_player(seat).ready_to_restart = true;
             check_for_restart_end(room);
         },
-        "game-options" => {
-            if room.game_started && room.game_state.current_phase != GamePhase::Restarting { return Err(String::from("Game in progress")); }
-            room.game_options = serde_json::from_value(msg_contents.get("options").unwrap().clone()).expect("Invalid option format");
+        ClientMessage::PlayerInfo(info) => {
+            if room.game_started && room.game_state.current_phase != GamePhase::Restarting { 
+                send_message(room.get_player(seat), ServerMessage::PlayerInfoRejected(String::from("The player info can't be changed during a game")));
+                return Ok(());
+            }
+
+            match info.validate() {
+                Ok(info) => {
+                    room.get_player(seat).player_info = Some(info);
+                    send_player_info(room, seat);
+                    spectators::send_spectator_state(room);
+                },
+                Err(reason) => send_message(room.get_player(seat), ServerMessage::PlayerInfoRejected(reason)),
+            }
+        },
+        ClientMessage::GameOptions { options } => {
+            if room.game_started && room.game_state.current_phase != GamePhase::Restarting { return Err(ProtocolError::new(ErrorCode::WrongPhase, "The options can't be changed during a game")); }
+            options.validate(&room.dictionaries.read().unwrap()).map_err(|err| ProtocolError::new(ErrorCode::InvalidOptions, err))?;
+
+            let player_count = room.players.iter().filter(|p| !p.left).count();
+            if options.max_players < player_count {
+                return Err(ProtocolError::new(ErrorCode::InvalidOptions, format!("There are already {} players in the room", player_count)));
+            }
+
+            if options.match_format != room.game_options.match_format {
+                series::reset(room);
+            }
+
+            room.game_options = options;
 
             if room.game_state.current_phase == GamePhase::Restarting {
-                // Tell the other player only if in restart phase. 
+                // Tell the other players only if in restart phase. 
                 // If the host changes the option for the first time, the options will be sent when the game starts
-                send_options(room.game_options.clone(), room.get_player(!is_host));
+                for other in (0..room.players.len()).filter(|other| *other != seat) {
+                    send_options(room.game_options.clone(), room.get_player(other));
+                }
             }
+
+            check_for_restart_end(room); // The room may now be full with fewer players
         },
-        _ => {
-            return Err(format!("Unknown message type {}", msg_type));
-        }
     }
     
     return Ok(());
 }
 
+fn send_done_to_other_players(room: &mut RoomState, seat: usize) {
+    for other in (0..room.players.len()).filter(|other| *other != seat) {
+        send_message(room.get_player(other), ServerMessage::OtherPlayerIsDone { seat });
+    }
+}
+
+/// The target can be omitted when the player has only one opponent
+fn handle_attack(room: &mut RoomState, attack: Attack, letter_id: u64, target: Option<usize>, seat: usize) -> Result<(), ProtocolError> {
+    if room.game_state.current_phase != GamePhase::Sabotaging {
+        return Err(ProtocolError::new(ErrorCode::WrongPhase, "Attack received during wrong phase"));
+    }
 
-pub fn send_options(options: GameOptions, player: &mut Player) {
-    #[derive(serde::Serialize, Clone)]
-    struct MessageType {
-        options: GameOptions,
+    if !room.get_player(seat).in_game {
+        return Err(ProtocolError::new(ErrorCode::WrongPhase, "You don't play this game"));
+    }
+
+    if letter_id >= room.game_options.word_length {
+        return Err(ProtocolError::new(ErrorCode::InvalidLetterIndex, format!("The letter index must be between 0 and {}", room.game_options.word_length - 1)));
+    }
+
+    let opponents = teams::get_opponents(room, seat);
+    let target = match target {
+        Some(target) if opponents.contains(&target) => target,
+        Some(_) => return Err(ProtocolError::new(ErrorCode::InvalidTarget, "This player can't be attacked")),
+        None if opponents.len() == 1 => opponents[0],
+        None => return Err(ProtocolError::new(ErrorCode::InvalidTarget, "The target is needed when there are several opponents")),
+    };
+
+    if !teams::is_captain(room, seat) { // Only a suggestion for the captain of the team
+        let choice = protocol::AttackChoice { attack, id: letter_id, target };
+        for member in teams::get_board_members(room, seat) {
+            if teams::is_captain(room, member) {
+                send_message(room.get_player(member), ServerMessage::TeammateSuggestion { seat, choice });
+                break;
+            }
+        }
+        return Ok(());
+    }
+
+    let options = room.game_options.clone();
+    let player = room.get_player(seat);
+
+    if player.attack_this_turn.is_some() {
+        return Err(ProtocolError::new(ErrorCode::ActionAlreadyDone, "Attack already received for this turn"));
     }
-    let current_options = MessageType { options: options };
-    send_message(player, "game-options", &current_options);
+
+    if !can_use_attack(&options, player, attack) {
+        send_message(player, ServerMessage::AttackRejected(attack));
+        return Ok(());
+    }
+
+    register_attack(player, PlayerAttack { attack, letter_id, target });
+    let ended = check_for_sabotage_end(room);
+
+    // Tell the other players
+    if !ended { 
+        send_done_to_other_players(room, seat);
+        start_phase_timer(room);
+    }
+
+    return Ok(());
 }
 
+pub fn send_session_token(player: &mut Player) {
+    let token = player.session_token.clone();
+    send_message(player, ServerMessage::SessionToken(token));
+}
+
+/// Sends to a reconnecting player everything needed to rebuild the game
+pub fn send_state_resync(room: &mut RoomState, seat: usize) {
+    let phase = room.game_state.current_phase;
+    let other_players = (0..room.players.len()).filter(|other| *other != seat).map(|other_seat| {
+        let other = &room.players[other_seat];
+        protocol::OtherPlayerState {
+            seat: other_seat,
+            team: other.team,
+            info: other.player_info.clone(),
+            connected: other.connection_alive,
+            forfeit_deadline: get_forfeit_deadline(room, other_seat).map(get_timestamp),
+            in_game: other.in_game,
+            past_words: other.past_words.clone(),
+            done_this_phase: match phase {
+                GamePhase::Typing => has_typed_or_skipped(room, other_seat),
+                GamePhase::Sabotaging => has_attacked(room, other_seat),
+                GamePhase::Restarting => other.ready_to_restart,
+            },
+        }
+    }).collect();
+
+    let player = &room.players[seat];
+    let msg = protocol::StateResync {
+        seat,
+        team: player.team,
+        game_started: room.game_started,
+        current_turn: room.game_state.current_turn,
+        current_phase: phase,
+        phase_deadline: room.game_state.phase_deadline.map(get_deadline_timestamp),
+        options: room.game_options.clone(),
+        in_game: player.in_game,
+        past_words: player.past_words.clone(),
+        past_hints: player.past_hints.clone(),
+        typed_word_this_turn: player.typed_word_this_turn.clone(),
+        attack_this_turn: player.attack_this_turn.map(|a| protocol::AttackChoice { attack: a.attack, id: a.letter_id, target: a.target }),
+        other_players,
+        match_score: series::get_current_score(room),
+    };
+
+    send_message(room.get_player(seat), ServerMessage::StateResync(msg));
+}
+
+/// Converts a phase deadline to the deadline shown to the players (without the grace period), in milliseconds since UNIX epoch
+fn get_deadline_timestamp(deadline: std::time::Instant) -> u128 {
+    get_timestamp(deadline.checked_sub(std::time::Duration::from_millis(TIMER_GRACE_PERIOD)).unwrap_or(deadline))
+}
+
+/// Converts an instant to milliseconds since UNIX epoch, to be sent to the players
+pub fn get_timestamp(instant: std::time::Instant) -> u128 {
+    let remaining = instant.saturating_duration_since(std::time::Instant::now());
+    (std::time::SystemTime::now() + remaining)
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0)
+}
+
+/// Sends the info of a player to the other players, if it is known
+pub fn send_player_info(room: &mut RoomState, from_seat: usize) {
+    let Some(info) = room.players[from_seat].player_info.clone() else {
+        return;
+    };
+
+    for other in (0..room.players.len()).filter(|other| *other != from_seat) {
+        send_message(room.get_player(other), ServerMessage::OtherPlayerInfo { seat: from_seat, info: info.clone() });
+    }
+}
+
+/// Sends to a player the info of all the other players, when they join or reconnect
+pub fn send_other_players_info(room: &mut RoomState, to_seat: usize) {
+    for other in (0..room.players.len()).filter(|other| *other != to_seat) {
+        if let Some(info) = room.players[other].player_info.clone() {
+            send_message(room.get_player(to_seat), ServerMessage::OtherPlayerInfo { seat: other, info });
+        }
+    }
+}
+
+pub fn send_options(options: GameOptions, player: &mut Player) {
+    send_message(player, ServerMessage::GameOptions { options });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Room of two players at the start of the first turn, with the word lists of the repository
+    fn start_test_room() -> RoomState {
+        let words_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../words");
+        let dictionaries = dictionary::load(&words_dir).unwrap();
+
+        let mut room = RoomState {
+            game_state: get_initial_game_state(),
+            players: vec![Player::new(None), Player::new(None)],
+            spectators: Vec::new(),
+            join_code: String::from("test-room"),
+            game_started: true,
+            game_options: GameOptions::default(),
+            game_count: 0,
+            series_games: 0,
+            series_over: false,
+            created_at: std::time::Instant::now(),
+            reconnect_grace_period: 0,
+            statistics: Arc::new(Mutex::new(None)),
+            dictionaries: Arc::new(RwLock::new(dictionaries)),
+        };
+        game_start(&mut room);
+
+        return room;
+    }
+
+    #[test]
+    fn teams_are_balanced_when_seats_are_empty() {
+        let mut room = start_test_room();
+        room.game_options.teams = true;
+        room.game_options.max_players = teams::TEAM_SIZE * teams::TEAM_COUNT;
+        room.game_state.current_phase = GamePhase::Restarting;
+        room.players = (0..6).map(|_| Player::new(None)).collect();
+        for player in room.players.iter_mut() {
+            player.ready_to_restart = true;
+        }
+
+        // 5 players can't make two full teams
+        room.players[1].left = true;
+        check_for_restart_end(&mut room);
+        assert!(room.game_state.current_phase == GamePhase::Restarting);
+
+        room.players[3].left = true;
+        check_for_restart_end(&mut room);
+        assert!(room.game_state.current_phase == GamePhase::Typing);
+
+        let teams: Vec<Option<usize>> = room.players.iter().map(|p| p.team).collect();
+        assert_eq!(teams, [Some(0), None, Some(1), None, Some(0), Some(1)]);
+    }
+
+    /// A word the players can type, other than the solution and the excluded word
+    fn get_other_word(room: &RoomState, excluded: &str) -> String {
+        let dictionaries = room.dictionaries.read().unwrap();
+        loop {
+            let word = dictionaries.get_random_secret_word(&room.game_options.language, room.game_options.word_length).unwrap();
+            if Some(&word) != room.game_state.word_to_guess.as_ref() && word != excluded {
+                return word;
+            }
+        }
+    }
+
+    fn send_word(room: &mut RoomState, seat: usize, word: &str) -> Result<(), ProtocolError> {
+        handle_one_message(room, ClientMessage::Word { word: word.to_string() }, seat)
+    }
+
+    fn send_sabotage(room: &mut RoomState, seat: usize, id: u64) -> Result<(), ProtocolError> {
+        handle_one_message(room, ClientMessage::Sabotage { id, target: None }, seat)
+    }
+
+    /// Both players type a word, to go to the sabotage phase
+    fn go_to_sabotage_phase(room: &mut RoomState) {
+        let word = get_other_word(room, "");
+        send_word(room, 0, &word).unwrap();
+        send_word(room, 1, &word).unwrap();
+        assert!(room.game_state.current_phase == GamePhase::Sabotaging);
+    }
+
+    #[test]
+    fn attack_outside_of_the_word_is_rejected() {
+        let mut room = start_test_room();
+        go_to_sabotage_phase(&mut room);
+
+        let word_length = room.game_options.word_length;
+        let err = send_sabotage(&mut room, 0, word_length).unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidLetterIndex);
+
+        let err = handle_one_message(&mut room, ClientMessage::Attack { attack: Attack::InvisibleSabotage, id: u64::MAX, target: None }, 0).unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidLetterIndex);
+        assert!(room.players[0].attack_this_turn.is_none());
+    }
+
+    #[test]
+    fn second_word_is_rejected_and_does_not_overwrite_the_first() {
+        let mut room = start_test_room();
+        let first = get_other_word(&room, "");
+        let second = get_other_word(&room, &first);
+
+        send_word(&mut room, 0, &first).unwrap();
+        let err = send_word(&mut room, 0, &second).unwrap_err();
+
+        assert_eq!(err.code, ErrorCode::ActionAlreadyDone);
+        assert_eq!(room.players[0].typed_word_this_turn, Some(first));
+    }
+
+    #[test]
+    fn second_attack_is_rejected_and_does_not_overwrite_the_first() {
+        let mut room = start_test_room();
+        go_to_sabotage_phase(&mut room);
+
+        send_sabotage(&mut room, 0, 1).unwrap();
+        let err = send_sabotage(&mut room, 0, 2).unwrap_err();
+
+        assert_eq!(err.code, ErrorCode::ActionAlreadyDone);
+        assert_eq!(room.players[0].attack_this_turn.map(|attack| attack.letter_id), Some(1));
+    }
+
+    #[test]
+    fn invalid_timers_are_rejected() {
+        let room = start_test_room();
+        let dictionaries = room.dictionaries.read().unwrap();
+
+        for timer in [f32::INFINITY, f32::NAN, -1.0, 1e20, MAX_TIMER + 1.0] {
+            let options = GameOptions { timer, ..GameOptions::default() };
+            assert!(options.validate(&dictionaries).is_err(), "timer {} accepted", timer);
+        }
+    }
+
+    #[test]
+    fn actions_in_the_wrong_phase_are_rejected() {
+        let mut room = start_test_room();
+
+        let err = send_sabotage(&mut room, 0, 0).unwrap_err();
+        assert_eq!(err.code, ErrorCode::WrongPhase);
+
+        go_to_sabotage_phase(&mut room);
+
+        let word = get_other_word(&room, "");
+        let err = send_word(&mut room, 0, &word).unwrap_err();
+        assert_eq!(err.code, ErrorCode::WrongPhase);
+        assert!(room.game_state.current_phase == GamePhase::Sabotaging);
+    }
+}
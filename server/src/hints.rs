@@ -1,57 +1,111 @@
+use std::collections::HashMap;
 
 
-pub fn get_hints(secret_word: &str, input_word: &str, sabotage_index: usize) -> Vec<crate::HintType> {
+
+/// Computes the hints for `input_word`. 
+/// `hidden_letters` are the indices of the attacked letters, and the hint to show instead (red for a sabotage, none for an invisible sabotage)
+pub fn get_hints(secret_word: &str, input_word: &str, hidden_letters: &[(usize, crate::HintType)]) -> Vec<crate::HintType> {
+    let is_hidden = |index: usize| hidden_letters.iter().any(|(i, _)| *i == index);
     let secret_chars: Vec<char> = secret_word.chars().collect();
-    let mut res = vec![crate::HintType::Gray; crate::game::WORD_LENGTH as usize];
+    let mut res = vec![crate::HintType::Gray; input_word.chars().count()];
 
-    let mut letter_counts = Vec::with_capacity(26);
-    for i in 0..26 {
-        let letter = ('A' as u8 + i) as char;
-        letter_counts.push(secret_chars.iter().filter(|c| **c == letter).count());
+    // Letters can be any character of the alphabet of the language, so count them in maps
+    let mut letter_counts: HashMap<char, usize> = HashMap::new();
+    for c in secret_chars.iter() {
+        *letter_counts.entry(*c).or_insert(0) += 1;
     }
 
-    let mut shown_counts = vec![0; 26];
+    let mut shown_counts: HashMap<char, usize> = HashMap::new();
 
     // Show greens
     for (i, char) in input_word.chars().enumerate() {
-        let id = (char as u8 - 'A' as u8) as usize;
-
-        if secret_chars[i] == char {
+        if secret_chars.get(i) == Some(&char) {
             res[i] = crate::HintType::Green;
 
-            if i != sabotage_index {
-                shown_counts[id] += 1;
+            if !is_hidden(i) {
+                *shown_counts.entry(char).or_insert(0) += 1;
             }
         }
     }
 
     // Show yellows
     for (i, char) in input_word.chars().enumerate() {
-        let id = (char as u8 - 'A' as u8) as usize;
+        let shown_count = shown_counts.get(&char).copied().unwrap_or(0);
+        let letter_count = letter_counts.get(&char).copied().unwrap_or(0);
 
-        if secret_chars[i] != char && shown_counts[id] < letter_counts[id] {
+        if secret_chars.get(i) != Some(&char) && shown_count < letter_count {
             res[i] = crate::HintType::Yellow;
             
-            if i != sabotage_index {
-                shown_counts[id] += 1;
+            if !is_hidden(i) {
+                shown_counts.insert(char, shown_count + 1);
             }
         }
     }
     
-    // Show red
-    res[sabotage_index] = crate::HintType::Red;
+    // Hide the attacked letters. If a letter is attacked several times, the last attack wins.
+    // Indexes outside of the word are ignored, they are refused earlier but must not crash the room
+    for (index, hint) in hidden_letters {
+        if let Some(res_hint) = res.get_mut(*index) {
+            *res_hint = *hint;
+        }
+    }
 
     return res;
 }
 
-pub fn get_hints_strings(hints: Vec<crate::HintType>) -> Vec<String> {
-    hints.iter().map(|h| {
-        String::from(match h {
-            crate::HintType::Gray => "gray",
-            crate::HintType::Green => "green",
-            crate::HintType::Yellow => "yellow",
-            crate::HintType::Red => "red",
-            crate::HintType::None => "none",
-        })
-    }).collect()
+/// Constraint of the hard mode that a word doesn't respect
+#[derive(serde::Serialize, Debug)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+pub enum HardModeViolation {
+    /// A letter revealed in green must stay at the same position
+    MissingGreen { letter: char, position: usize },
+    /// A letter revealed in yellow must be used again (as many times as revealed)
+    MissingYellow { letter: char },
+}
+
+/// Checks that `input_word` uses all the hints revealed by the previous words. 
+/// A red (sabotaged) letter doesn't reveal anything, so it is not taken into account.
+pub fn check_hard_mode(input_word: &str, past_words: &[String], past_hints: &[Vec<crate::HintType>]) -> Result<(), HardModeViolation> {
+    let input_chars: Vec<char> = input_word.chars().collect();
+
+    for (word, hints) in past_words.iter().zip(past_hints.iter()) {
+        // Greens must be at the same place
+        for (i, (char, hint)) in word.chars().zip(hints.iter()).enumerate() {
+            if *hint == crate::HintType::Green && input_chars.get(i) != Some(&char) {
+                return Err(HardModeViolation::MissingGreen { letter: char, position: i });
+            }
+        }
+
+        // Each revealed letter must be present at least as many times as it was revealed
+        for (char, _) in word.chars().zip(hints.iter()).filter(|(_, h)| **h == crate::HintType::Yellow) {
+            let revealed_count = word.chars().zip(hints.iter())
+                .filter(|(c, h)| *c == char && (**h == crate::HintType::Green || **h == crate::HintType::Yellow))
+                .count();
+            let used_count = input_chars.iter().filter(|c| **c == char).count();
+
+            if used_count < revealed_count {
+                return Err(HardModeViolation::MissingYellow { letter: char });
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HintType;
+
+    #[test]
+    fn hidden_letter_outside_of_the_word_is_ignored() {
+        let hints = get_hints("CRANE", "CRATE", &[(5, HintType::Red), (usize::MAX, HintType::None)]);
+        assert_eq!(hints, vec![HintType::Green, HintType::Green, HintType::Green, HintType::Gray, HintType::Green]);
+    }
+
+    #[test]
+    fn hidden_letters_replace_the_hints() {
+        let hints = get_hints("CRANE", "CRATE", &[(0, HintType::Red), (3, HintType::None)]);
+        assert_eq!(hints, vec![HintType::Red, HintType::Green, HintType::Green, HintType::None, HintType::Green]);
+    }
 }
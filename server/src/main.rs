@@ -1,67 +1,115 @@
 
 #![allow(dead_code)]
+#![allow(clippy::needless_return)]
 
 mod util;
 mod game;
 mod server_internal;
 mod hints;
 mod statistics;
+mod dictionary;
+mod room_views;
+mod protocol;
+mod room_actor;
+mod reaper;
+mod config;
+mod spectators;
+mod teams;
+mod series;
 
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
 use actix_web::HttpResponse;
 use actix_web::web;
 
-use crate::game::MAX_WORD_COUNT;
-
-#[derive(serde::Serialize, PartialEq, Eq)]
+#[derive(serde::Serialize, PartialEq, Eq, Clone, Copy)]
 enum GamePhase {
     Typing, Sabotaging, Restarting
 }
 
-#[derive(PartialEq, Eq, Clone, Copy)]
+#[derive(serde::Serialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
 enum HintType {
     Green, Yellow, Red, Gray, None
 }
 
-#[derive(serde::Serialize)]
 struct GameState {
     word_to_guess: Option<String>,
     current_turn: i64,
-    current_phase: GamePhase
+    current_phase: GamePhase,
+    phase_deadline: Option<std::time::Instant>, // None if the timer is not running for the current phase
 }
 
-// NOTE: do not rename these enums' values! Serialization depend on the names.
-#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq, Hash)]
-enum Language {
-    French, English
-}
-#[derive(serde::Deserialize)]
+/// Code of a language, as described in `languages.json`
+// NOTE: do not rename the codes of existing languages! Options and statistics depend on them.
+#[derive(serde::Deserialize, serde::Serialize, Clone, PartialEq, Eq, Hash, Debug)]
+#[serde(transparent)]
+struct Language(String);
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq)]
 enum Mode {
-    Normal, Hard
+    Normal, 
+    Hard, // Revealed hints must be used in the next words
 }
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
 enum Attack {
-    Sabotage, InvisibleSabotage, Espionage
+    Sabotage, // The hint is replaced by red
+    InvisibleSabotage, // The hint is hidden, without showing red
+    Espionage, // The hint is revealed to the attacker instead
 }
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
 enum AttackMode {
-    OncePerTurn,
+    OncePerTurn, // Only sabotages
     Multiple {
-        available_attacks: Vec<Attack>
+        available_attacks: Vec<Attack>,
+        #[serde(default = "HashMap::new")]
+        attack_budgets: HashMap<Attack, u64>, // How many times each attack can be used in one game. Unlimited if absent
     }
 }
 
+/// How many games are played in a series, see `series`
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+enum MatchFormat {
+    Single, // No series, each game is independent
+    BestOf(u64), // The series ends when a player can't be caught up, or after this number of games
+    FirstTo(u64), // The series ends when a player reaches this number of wins
+}
+
+/// Attack chosen by a player during the sabotage phase
+#[derive(Clone, Copy)]
+struct PlayerAttack {
+    attack: Attack,
+    letter_id: u64,
+    target: usize, // Seat of the attacked player
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
 struct GameOptions {
     timer: f32, // 0 iff the timer is disabled
     language: Language,
+    #[serde(default = "normal_mode")]
+    mode: Mode,
+    #[serde(default = "once_per_turn")]
+    attack_mode: AttackMode,
+    #[serde(default = "default_word_length")]
+    word_length: u64,
+    #[serde(default = "default_max_word_count")]
+    max_word_count: u64, // How many guesses each player has
+    #[serde(default = "default_max_players")]
+    max_players: usize, // The game starts when this number of players is reached
+    #[serde(default)]
+    teams: bool, // Two teams of two players, see `teams`
+    #[serde(default = "single_game")]
+    match_format: MatchFormat,
 }
 
 /// Settings for a player (thins that the player has configured)
-#[derive(serde::Serialize, Clone)]
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
 struct PlayerInfo {
     name: String,
+    #[serde(default)]
+    avatar: Option<String>, // Identifier of the avatar chosen by the player
+    #[serde(default)]
+    color: Option<String>, // "#rrggbb"
 }
 
 struct SocketConnection {
@@ -70,74 +118,92 @@ struct SocketConnection {
 }
 
 /// Think we know about a player in a game
-#[derive(serde::Serialize)]
 struct Player {
     player_info: Option<PlayerInfo>, // None if not sent by the player yet
-    messages_to_send: Vec<String>,
+    session_token: String, // Secret sent only to the player, needed to reconnect
+    messages_to_send: Vec<String>, // Messages not sent yet, kept while the player is disconnected
+    outbox: Option<room_actor::Outbox>, // Connection of the player, None if disconnected
+    connection_id: u64, // Id of the current connection, to ignore the messages of the previous ones
     connection_alive: bool,
-    #[serde(skip_serializing)]
+    disconnected_since: Option<std::time::Instant>, // None if connected
+    left: bool, // Closed the connection on purpose, can't reconnect
+    in_game: bool, // Plays the current game. False if they joined between two games or forfeited
+    team: Option<usize>, // Team in the current game, None without teams
+    series_wins: u64, // Games won in the current series
     last_ping_time: std::time::Instant,
     typed_word_this_turn: Option<String>,
-    letter_sabotaged_this_turn: Option<u64>,
+    turn_skipped: bool, // The player didn't type a word before the end of the timer
+    attack_this_turn: Option<PlayerAttack>,
+    attacks_used: HashMap<Attack, u64>, // How many times each attack was used in this game
     past_words: Vec<String>,
+    past_hints: Vec<Vec<HintType>>, // Hints received for each word of `past_words`
     ready_to_restart: bool,
 }
 
-#[derive(serde::Serialize)]
+/// Seat of the player who created the room
+const HOST_SEAT: usize = 0;
+
+/// Never sent as is: see `room_views` for what can be shown outside of the game
 struct RoomState {
     game_state: GameState,
-    host_player: Player,
-    other_player: Option<Player>,
+    players: Vec<Player>, // Indexed by seat. Seats are kept when players leave, and given to the next players joining
+    spectators: Vec<spectators::Spectator>,
     join_code: String,
     game_started: bool,
     game_options: GameOptions,
     game_count: u64, // How many games were played before?
+    series_games: u64, // Games played in the current series
+    series_over: bool, // The next game starts a new series
+    created_at: std::time::Instant,
+    reconnect_grace_period: u64, // Seconds a disconnected player has to come back before forfeiting, 0 to wait forever
 
-    #[serde(skip)]
     statistics: statistics::StatsHandle,
+    dictionaries: dictionary::DictionariesHandle,
 }
 
 struct AppState {
-    rooms: Mutex<HashMap<String, Arc<Mutex<RoomState>>>>,
-    statistics: statistics::StatsHandle
+    rooms: Mutex<HashMap<String, room_actor::RoomHandle>>, // The rooms themselves are owned by their tasks
+    statistics: statistics::StatsHandle,
+    dictionaries: dictionary::DictionariesHandle,
 }
 
 type ProtectedAppState = std::sync::LazyLock<Arc<AppState>>;
 
 #[actix_web::get("/create-room")]
 async fn create_room(req: actix_web::HttpRequest, stream: web::Payload, data: web::Data<&ProtectedAppState>) -> impl actix_web::Responder {
-    // Before creating a new room, check if some rooms can be deleted
-    let mutex_ok = match data.rooms.lock() {
-        Ok(mut rooms) => {
-            server_internal::remove_empty_rooms(&mut rooms);
-            true
-        },
-        Err(_) => {
-            println!("If we go in this branch, the server probably went through a lot of problems... All rooms will be deleted");
-            false
-        }
-    };
-
-    if !mutex_ok { // If the mutex was poisoned, reset the rooms
-        data.rooms.clear_poison();
-        *data.rooms.lock().unwrap() = HashMap::new();
+    let max_rooms = config::get().max_rooms;
+    if max_rooms > 0 && util::lock(&data.rooms).len() >= max_rooms {
+        return Ok::<HttpResponse, actix_web::Error>(HttpResponse::ServiceUnavailable().body("Too many rooms, try again later"));
     }
 
-    let code = util::create_random_code();
+    let code = util::create_random_code(&data.dictionaries.read().unwrap());
+
+    let (response, connection) = server_internal::start_websocket(req, stream)?;
+    let (outbox, outbox_receiver) = tokio::sync::mpsc::unbounded_channel();
+
+    let mut host_player = Player::new(None);
+    let connection_id = server_internal::attach_connection(&mut host_player, outbox);
+    game::send_session_token(&mut host_player);
+    server_internal::send_message(&mut host_player, protocol::ServerMessage::RoomCode(code.clone()));
+    server_internal::send_message(&mut host_player, protocol::ServerMessage::Seat(HOST_SEAT));
 
-    let test_info = PlayerInfo { name: String::from("John client 1") };
     let new_room = RoomState {
         game_state: game::get_initial_game_state(),
-        host_player: Player::new(Some(test_info)),
-        other_player: None,
+        players: vec![host_player],
+        spectators: Vec::new(),
         join_code: code.clone(),
         game_started: false,
         game_options: GameOptions::default(),
         statistics: data.statistics.clone(),
+        dictionaries: data.dictionaries.clone(),
         game_count: 0,
+        series_games: 0,
+        series_over: false,
+        created_at: std::time::Instant::now(),
+        reconnect_grace_period: config::get().reconnect_grace_period,
     };
 
-    let active_room_count = data.rooms.lock().unwrap().len() + 1;
+    let active_room_count = util::lock(&data.rooms).len() + 1;
 
     println!("Room creation request: {}. Now there are {} rooms active.", new_room.join_code, active_room_count);
 
@@ -145,13 +211,9 @@ async fn create_room(req: actix_web::HttpRequest, stream: web::Payload, data: we
         stats.max_room_active_at_same_time = u64::max(stats.max_room_active_at_same_time, active_room_count as u64);
     });
 
-    let room_in_arc = Arc::new(Mutex::new(new_room));
-
-    data.rooms.lock().unwrap().insert(code.clone(), Arc::clone(&room_in_arc));
-    
-    let (response, connection) = server_internal::start_websocket(req, stream)?;
-    server_internal::handle_player_connection(Arc::clone(&room_in_arc), true, connection).await.unwrap();
-    server_internal::send_message(&mut room_in_arc.lock().unwrap().host_player, "room-code", &code);
+    let room = room_actor::start_room(new_room);
+    util::lock(&data.rooms).insert(code, room.clone());
+    server_internal::handle_connection(room, room_actor::ConnectionRole::Player { seat: HOST_SEAT }, connection_id, connection, outbox_receiver);
 
     Ok::<HttpResponse, actix_web::Error>(response)
 }
@@ -159,85 +221,153 @@ async fn create_room(req: actix_web::HttpRequest, stream: web::Payload, data: we
 #[actix_web::get("/join-room/{room_code}")]
 async fn join_room(req: actix_web::HttpRequest, stream: web::Payload, data: web::Data<&ProtectedAppState>, path: web::Path<String>) -> impl actix_web::Responder {
     let room_code = path.into_inner();
+    let Some(room) = util::lock(&data.rooms).get(&room_code).cloned() else {
+        return Ok::<HttpResponse, actix_web::Error>(get_join_error_response(room_actor::JoinError::RoomNotFound));
+    };
 
-    if data.rooms.lock().unwrap().contains_key(&room_code) { // Room exists
-        let room = Arc::clone(&data.rooms.lock().unwrap()[&room_code]);
+    let (response, connection) = server_internal::start_websocket(req, stream)?;
+    let (outbox, outbox_receiver) = tokio::sync::mpsc::unbounded_channel();
 
-        {
-            let room_ref = room.lock().unwrap();
-            if room_ref.game_started || (room_ref.other_player.is_some() && room_ref.other_player.as_ref().unwrap().connection_alive) { // Room already full
-                return Ok::<HttpResponse, actix_web::Error>(HttpResponse::BadRequest().body("Room already full"));
-            }
-        }
-        
-        println!("Player is joining room {}", room_code);
+    let result = room.request(|reply| room_actor::RoomCommand::Join { outbox, reply }).await
+        .unwrap_or(Err(room_actor::JoinError::RoomNotFound));
 
-        let (response, connection) = server_internal::start_websocket(req, stream)?;
+    match result {
+        Ok((seat, connection_id)) => {
+            server_internal::handle_connection(room, room_actor::ConnectionRole::Player { seat }, connection_id, connection, outbox_receiver);
+            Ok::<HttpResponse, actix_web::Error>(response)
+        },
+        Err(err) => Ok::<HttpResponse, actix_web::Error>(get_join_error_response(err)),
+    }
+}
 
-        let test_info = PlayerInfo { name: String::from("John client 2") };
-        let mut player = Player::new(Some(test_info));
-        player.ready_to_restart = true; // Player is immediately ready
+#[derive(serde::Deserialize)]
+struct ReconnectQuery {
+    token: String, // Session token sent to the player when they joined
+}
 
-        room.lock().unwrap().other_player = Some(player); // Add player to room
-        server_internal::handle_player_connection(Arc::clone(&room), false, connection).await.unwrap(); // Start handling connection
-        server_internal::send_message(&mut room.lock().unwrap().host_player, "other-player-connected", &()); // Tell the other player
-        
-        if room.lock().unwrap().host_player.ready_to_restart {
-            game::check_for_restart_end(&mut room.lock().unwrap());
-        }
-        else {
-            server_internal::send_message(&mut room.lock().unwrap().get_player(false), "wait-for-host", &());
-        }
+/// `seat` is the one sent to the player in the `seat` message
+#[actix_web::get("/reconnect/{seat}/{room_code}")]
+async fn reconnect(req: actix_web::HttpRequest, stream: web::Payload, data: web::Data<&ProtectedAppState>, path: web::Path<(usize, String)>, query: web::Query<ReconnectQuery>) -> impl actix_web::Responder {
+    let (seat, room_code) = path.into_inner();
 
-        Ok::<HttpResponse, actix_web::Error>(response)
-    }
-    else {
-        Ok::<HttpResponse, actix_web::Error>(HttpResponse::NotFound().body("No room with this code"))
-    }
-}
+    println!("Reconnection of player {} in {}", seat, room_code);
 
-#[actix_web::get("/reconnect/{which_player}/{room_code}")]
-async fn reconnect(req: actix_web::HttpRequest, stream: web::Payload, data: web::Data<&ProtectedAppState>, path: web::Path<(u32, String)>) -> impl actix_web::Responder {
-    let (which_player, room_code) = path.into_inner();
-    let is_host_player = which_player == 0;
+    let Some(room) = util::lock(&data.rooms).get(&room_code).cloned() else {
+        return Ok::<HttpResponse, actix_web::Error>(get_join_error_response(room_actor::JoinError::RoomNotFound));
+    };
 
-    println!("Reconnection of player {} in {}", which_player, room_code);
+    let (response, connection) = server_internal::start_websocket(req, stream)?;
+    let (outbox, outbox_receiver) = tokio::sync::mpsc::unbounded_channel();
 
-    if data.rooms.lock().unwrap().contains_key(&room_code) { // Room exists
-        let room = Arc::clone(&data.rooms.lock().unwrap()[&room_code]);
-        let mut locked_room = room.lock().unwrap();
+    let token = query.into_inner().token;
+    let result = room.request(|reply| room_actor::RoomCommand::Reconnect { seat, token, outbox, reply }).await
+        .unwrap_or(Err(room_actor::JoinError::RoomNotFound));
 
-        if !locked_room.player_exists(is_host_player) || locked_room.get_player(is_host_player).connection_alive {
-            return Ok::<HttpResponse, actix_web::Error>(HttpResponse::BadRequest().body("Player not disconnected"));
-        }
-        
-        let player = locked_room.get_player(is_host_player);
-        let (response, connection) = server_internal::start_websocket(req, stream)?;
+    match result {
+        Ok(connection_id) => {
+            server_internal::handle_connection(room, room_actor::ConnectionRole::Player { seat }, connection_id, connection, outbox_receiver);
+            Ok::<HttpResponse, actix_web::Error>(response)
+        },
+        Err(err) => Ok::<HttpResponse, actix_web::Error>(get_join_error_response(err)),
+    }
+}
 
-        player.connection_alive = true;
-        player.last_ping_time = std::time::Instant::now();
-        server_internal::handle_player_connection(Arc::clone(&room), is_host_player, connection).await.unwrap();
+/// Watch a room without playing, see `spectators`
+#[actix_web::get("/spectate-room/{room_code}")]
+async fn spectate_room(req: actix_web::HttpRequest, stream: web::Payload, data: web::Data<&ProtectedAppState>, path: web::Path<String>) -> impl actix_web::Responder {
+    let room_code = path.into_inner();
+    let Some(room) = util::lock(&data.rooms).get(&room_code).cloned() else {
+        return Ok::<HttpResponse, actix_web::Error>(get_join_error_response(room_actor::JoinError::RoomNotFound));
+    };
 
-        Ok::<HttpResponse, actix_web::Error>(response)
+    let (response, connection) = server_internal::start_websocket(req, stream)?;
+    let (outbox, outbox_receiver) = tokio::sync::mpsc::unbounded_channel();
+
+    let result = room.request(|reply| room_actor::RoomCommand::Spectate { outbox, reply }).await
+        .unwrap_or(Err(room_actor::JoinError::RoomNotFound));
+
+    match result {
+        Ok(connection_id) => {
+            server_internal::handle_connection(room, room_actor::ConnectionRole::Spectator, connection_id, connection, outbox_receiver);
+            Ok::<HttpResponse, actix_web::Error>(response)
+        },
+        Err(err) => Ok::<HttpResponse, actix_web::Error>(get_join_error_response(err)),
     }
-    else {
-        Ok::<HttpResponse, actix_web::Error>(HttpResponse::NotFound().body("No room with this code"))
+}
+
+fn get_join_error_response(err: room_actor::JoinError) -> HttpResponse {
+    match err {
+        room_actor::JoinError::RoomNotFound => HttpResponse::NotFound().body("No room with this code"),
+        room_actor::JoinError::RoomFull => HttpResponse::BadRequest().body("Room already full"),
+        room_actor::JoinError::InvalidToken => HttpResponse::Forbidden().body("Invalid session token"),
+        room_actor::JoinError::NotDisconnected => HttpResponse::BadRequest().body("Player not disconnected"),
+        room_actor::JoinError::PlayerLeft => HttpResponse::Gone().body("Player left the room"),
+        room_actor::JoinError::TooManySpectators => HttpResponse::BadRequest().body("Too many spectators"),
     }
 }
 
+/// Public information about a room, without anything that could help to cheat
 #[actix_web::get("/room-state/{room_code}")]
 async fn get_room_state(data: web::Data<&ProtectedAppState>, path: web::Path<String>) -> impl actix_web::Responder {
     let room_code = path.into_inner();
+    let room = util::lock(&data.rooms).get(&room_code).cloned();
+
+    let view = match room {
+        Some(room) => room.request(|reply| room_actor::RoomCommand::GetPublicView { reply }).await,
+        None => None,
+    };
+
+    match view {
+        Some(view) => Ok::<HttpResponse, actix_web::Error>(HttpResponse::Ok().json(view)),
+        None => Ok::<HttpResponse, actix_web::Error>(HttpResponse::NotFound().body("No room with this code")),
+    }
+}
+
+/// Full state of a room (including the word to guess), for debugging
+#[actix_web::get("/admin/room-state/{room_code}")]
+async fn get_admin_room_state(req: actix_web::HttpRequest, data: web::Data<&ProtectedAppState>, path: web::Path<String>) -> impl actix_web::Responder {
+    if !server_internal::is_admin_request(&req) {
+        return Ok::<HttpResponse, actix_web::Error>(HttpResponse::Forbidden().body("Invalid admin token"));
+    }
+
+    let room_code = path.into_inner();
+    let room = util::lock(&data.rooms).get(&room_code).cloned();
+
+    let view = match room {
+        Some(room) => room.request(|reply| room_actor::RoomCommand::GetAdminView { reply }).await,
+        None => None,
+    };
 
-    if data.rooms.lock().unwrap().contains_key(&room_code) {
-        let room = Arc::clone(&data.rooms.lock().unwrap()[&room_code]);
-        Ok::<HttpResponse, actix_web::Error>(HttpResponse::Ok().body(serde_json::to_string_pretty(room.as_ref()).unwrap()))
+    match view {
+        Some(view) => Ok::<HttpResponse, actix_web::Error>(HttpResponse::Ok().json(view)),
+        None => Ok::<HttpResponse, actix_web::Error>(HttpResponse::NotFound().body("No room with this code")),
     }
-    else {
-        Ok::<HttpResponse, actix_web::Error>(HttpResponse::NotFound().body("No room with this code"))
+}
+
+#[actix_web::post("/admin/reload-dictionaries")]
+async fn reload_dictionaries(req: actix_web::HttpRequest, data: web::Data<&ProtectedAppState>) -> impl actix_web::Responder {
+    if !server_internal::is_admin_request(&req) {
+        return Ok::<HttpResponse, actix_web::Error>(HttpResponse::Forbidden().body("Invalid admin token"));
+    }
+
+    match dictionary::reload(&data.dictionaries) {
+        Ok(()) => {
+            println!("Dictionaries reloaded");
+            Ok::<HttpResponse, actix_web::Error>(HttpResponse::Ok().body("Dictionaries reloaded"))
+        },
+        Err(err) => {
+            log::error!("Couldn't reload dictionaries: {}", err);
+            Ok::<HttpResponse, actix_web::Error>(HttpResponse::InternalServerError().body(err))
+        },
     }
 }
 
+#[actix_web::get("/languages")]
+async fn get_languages(data: web::Data<&ProtectedAppState>) -> impl actix_web::Responder {
+    let languages = data.dictionaries.read().unwrap().get_languages_description();
+    Ok::<HttpResponse, actix_web::Error>(HttpResponse::Ok().json(languages))
+}
+
 #[actix_web::get("/ping")]
 async fn ping() -> impl actix_web::Responder {
     Ok::<HttpResponse, actix_web::Error>(HttpResponse::Ok().body(""))
@@ -246,29 +376,46 @@ async fn ping() -> impl actix_web::Responder {
 static APP_DATA: ProtectedAppState = std::sync::LazyLock::new(|| Arc::new(AppState { 
     rooms: Mutex::new(HashMap::new()),
     statistics: Arc::new(Mutex::new(statistics::load())),
+    dictionaries: Arc::new(RwLock::new(dictionary::Dictionaries::empty())),
 }));
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    env_logger::init();
+    // Must be done first, the app data depends on it
+    let config = match config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Invalid configuration: {}", err);
+            std::process::exit(2);
+        },
+    };
 
-    let args: Vec<String> = std::env::args().collect();
+    env_logger::Builder::new().filter_level(config.log_level).init();
 
     println!("Starting backend server!");
 
-    let port = match args.iter().position(|a| a == "--port") {
-        Some(p) => &args[p+1],
-        None => "4268",
-    };
-    
-    let address = if args.contains(&String::from("--localhost")) {
-        format!("0.0.0.0:{}", port)
+    let words_dir = &config.words_dir;
+
+    match dictionary::load(words_dir) {
+        Ok(dictionaries) => *APP_DATA.dictionaries.write().unwrap() = dictionaries,
+        Err(err) => {
+            log::error!("Couldn't load dictionaries: {}", err);
+            return Err(std::io::Error::other(err));
+        }
     }
-    else {
-        format!("[2a09:6847:fa10:1410::278]:{}", port)
-    };
 
-    println!("Serving on {}", address);
+    println!("Dictionaries loaded from {}", words_dir.display());
+
+    #[cfg(unix)]
+    server_internal::reload_dictionaries_on_sighup(Arc::clone(&APP_DATA.dictionaries))?;
+
+    reaper::start_reaper(&APP_DATA, config.reaper);
+
+    let addresses: Vec<std::net::SocketAddr> = config.bind.iter().map(|ip| std::net::SocketAddr::new(*ip, config.port)).collect();
+
+    for address in &addresses {
+        println!("Serving on {}", address);
+    }
 
     actix_web::HttpServer::new(|| {
         actix_web::App::new()
@@ -276,37 +423,26 @@ async fn main() -> std::io::Result<()> {
             .service(create_room)
             .service(join_room)
             .service(reconnect)
+            .service(spectate_room)
             .service(get_room_state)
+            .service(get_admin_room_state)
+            .service(reload_dictionaries)
+            .service(get_languages)
             .service(ping)
     })
-    .bind(address)?
+    .bind(&addresses[..])?
     .run()
     .await
 }
 
 
 impl RoomState {
-    pub fn player_exists(&self, get_host_player: bool) -> bool {
-        if get_host_player {
-            true
-        }
-        else {
-            self.other_player.is_some()
-        }
+    pub fn player_exists(&self, seat: usize) -> bool {
+        seat < self.players.len()
     }
 
-    pub fn get_player(&mut self, get_host_player: bool) -> &mut Player {
-        if get_host_player {
-            &mut self.host_player
-        }
-        else {
-            self.other_player.as_mut().unwrap()
-        }
-    }
-
-    pub fn do_for_all_players(&mut self, f: &dyn Fn(&mut Player, &mut Player) -> ()) {
-        f(&mut self.host_player, self.other_player.as_mut().unwrap());
-        f(self.other_player.as_mut().unwrap(), &mut self.host_player);
+    pub fn get_player(&mut self, seat: usize) -> &mut Player {
+        &mut self.players[seat]
     }
 }
 
@@ -314,19 +450,166 @@ impl Player {
     pub fn new(info: Option<PlayerInfo>) -> Player {
         Player { 
             player_info: info, 
+            session_token: util::create_session_token(),
             messages_to_send: Vec::new(),
+            outbox: None,
+            connection_id: 0,
             connection_alive: true,
+            disconnected_since: None,
+            left: false,
+            in_game: false,
+            team: None,
+            series_wins: 0,
             last_ping_time: std::time::Instant::now(),
-            past_words: Vec::with_capacity(MAX_WORD_COUNT as usize),
+            past_words: Vec::new(),
+            past_hints: Vec::new(),
             typed_word_this_turn: None,
-            letter_sabotaged_this_turn: None,
+            turn_skipped: false,
+            attack_this_turn: None,
+            attacks_used: HashMap::new(),
             ready_to_restart: false,
         }
     }
 }
 
+/// Maximum length of the name of a player, in characters
+const MAX_PLAYER_NAME_LENGTH: usize = 20;
+const MAX_PLAYER_AVATAR_LENGTH: usize = 32;
+
+impl PlayerInfo {
+    /// Returns the info with the name trimmed, or an explanation if the info is invalid
+    pub fn validate(&self) -> Result<PlayerInfo, String> {
+        let name = self.name.trim();
+
+        if name.is_empty() || name.chars().count() > MAX_PLAYER_NAME_LENGTH {
+            return Err(format!("The name must have between 1 and {} characters", MAX_PLAYER_NAME_LENGTH));
+        }
+
+        if name.chars().any(|c| c.is_control()) {
+            return Err(String::from("The name contains invalid characters"));
+        }
+
+        let avatar_ok = |avatar: &String| !avatar.is_empty() && avatar.chars().count() <= MAX_PLAYER_AVATAR_LENGTH && !avatar.chars().any(|c| c.is_control());
+        if !self.avatar.as_ref().is_none_or(avatar_ok) {
+            return Err(String::from("Invalid avatar"));
+        }
+
+        if let Some(color) = &self.color {
+            let is_hex_color = color.len() == 7 && color.starts_with('#') && color[1..].chars().all(|c| c.is_ascii_hexdigit());
+            if !is_hex_color {
+                return Err(String::from("The color must be in the format #rrggbb"));
+            }
+        }
+
+        Ok(PlayerInfo { name: String::from(name), avatar: self.avatar.clone(), color: self.color.clone() })
+    }
+}
+
+impl Language {
+    pub fn default_language() -> Language {
+        Language(String::from(dictionary::DEFAULT_LANGUAGE))
+    }
+}
+
 impl GameOptions {
     pub fn default() -> GameOptions {
-        GameOptions { timer: 0.0, language: Language::English }
+        GameOptions { 
+            timer: 0.0, 
+            language: Language::default_language(), 
+            mode: Mode::Normal, 
+            attack_mode: AttackMode::OncePerTurn,
+            word_length: game::DEFAULT_WORD_LENGTH,
+            max_word_count: game::DEFAULT_MAX_WORD_COUNT,
+            max_players: game::DEFAULT_MAX_PLAYERS,
+            teams: false,
+            match_format: MatchFormat::Single,
+        }
     }
+
+    /// Checks that the options can be used for a game
+    pub fn validate(&self, dictionaries: &dictionary::Dictionaries) -> Result<(), String> {
+        if !dictionaries.has_word_lists(&self.language, self.word_length) {
+            return Err(format!("No word list for words of {} letters in this language", self.word_length));
+        }
+
+        if !self.timer.is_finite() || self.timer < 0.0 || self.timer > game::MAX_TIMER {
+            return Err(format!("The timer must be between 0 (disabled) and {} seconds", game::MAX_TIMER));
+        }
+
+        if self.max_word_count == 0 || self.max_word_count > game::MAX_MAX_WORD_COUNT {
+            return Err(format!("The number of guesses must be between 1 and {}", game::MAX_MAX_WORD_COUNT));
+        }
+
+        let max_players = config::get().max_players;
+        if self.max_players < 2 || self.max_players > max_players {
+            return Err(format!("The number of players must be between 2 and {}", max_players));
+        }
+
+        match self.match_format {
+            MatchFormat::Single => {},
+            MatchFormat::BestOf(games) => {
+                if games % 2 == 0 || games > series::MAX_SERIES_LENGTH {
+                    return Err(format!("A series must be played in an odd number of games, up to {}", series::MAX_SERIES_LENGTH));
+                }
+            },
+            MatchFormat::FirstTo(wins) => {
+                if wins == 0 || wins > series::MAX_SERIES_LENGTH {
+                    return Err(format!("The number of wins of a series must be between 1 and {}", series::MAX_SERIES_LENGTH));
+                }
+            },
+        }
+
+        let team_players = teams::TEAM_SIZE * teams::TEAM_COUNT;
+        if self.teams && self.max_players != team_players {
+            return Err(format!("Team games need {} players", team_players));
+        }
+
+        if let AttackMode::Multiple { available_attacks, attack_budgets } = &self.attack_mode {
+            // Make sure players can always attack, even after using all their limited attacks
+            if !available_attacks.iter().any(|a| !attack_budgets.contains_key(a)) {
+                return Err(String::from("At least one available attack must have an unlimited budget"));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn is_attack_available(&self, attack: Attack) -> bool {
+        match &self.attack_mode {
+            AttackMode::OncePerTurn => attack == Attack::Sabotage,
+            AttackMode::Multiple { available_attacks, .. } => available_attacks.contains(&attack),
+        }
+    }
+
+    /// How many times an attack can be used in one game, None if unlimited
+    pub fn get_attack_budget(&self, attack: Attack) -> Option<u64> {
+        match &self.attack_mode {
+            AttackMode::OncePerTurn => None,
+            AttackMode::Multiple { attack_budgets, .. } => attack_budgets.get(&attack).copied(),
+        }
+    }
+}
+
+fn normal_mode() -> Mode {
+    Mode::Normal
+}
+
+fn once_per_turn() -> AttackMode {
+    AttackMode::OncePerTurn
+}
+
+fn default_word_length() -> u64 {
+    game::DEFAULT_WORD_LENGTH
+}
+
+fn default_max_word_count() -> u64 {
+    game::DEFAULT_MAX_WORD_COUNT
+}
+
+fn single_game() -> MatchFormat {
+    MatchFormat::Single
+}
+
+fn default_max_players() -> usize {
+    game::DEFAULT_MAX_PLAYERS
 }
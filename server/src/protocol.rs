@@ -0,0 +1,206 @@
+use crate::game::GameEnd;
+use crate::{Attack, GameOptions, GamePhase, HintType, MatchFormat, PlayerInfo};
+use crate::hints::HardModeViolation;
+use crate::reaper::ExpiryReason;
+
+/// Messages sent by the clients, as described in `api.md`
+#[derive(serde::Deserialize)]
+#[serde(tag = "type", content = "content", rename_all = "kebab-case")]
+pub enum ClientMessage {
+    Ping {},
+    Word { word: String },
+    Sabotage { id: u64, #[serde(default)] target: Option<usize> }, // Same as an `Attack` with `Attack::Sabotage`
+    Attack { attack: Attack, id: u64, #[serde(default)] target: Option<usize> }, // Seat of the attacked player, can be omitted if there is only one opponent
+    Resign {}, // Gives up the current game, with the teammate in team mode
+    RestartReady {},
+    PlayerInfo(PlayerInfo),
+    GameOptions { options: GameOptions },
+}
+
+/// Messages sent to the clients, as described in `api.md`
+#[derive(serde::Serialize)]
+#[serde(tag = "type", content = "content", rename_all = "kebab-case")]
+pub enum ServerMessage {
+    RoomCode(String),
+    SessionToken(String),
+    Seat(usize), // Seat of the player in the room, needed to reconnect. The host is at seat 0
+    StateResync(StateResync),
+    OtherPlayerInfo { seat: usize, #[serde(flatten)] info: PlayerInfo },
+    PlayerInfoRejected(String), // Explanation
+    OtherPlayerConnected { seat: usize },
+    OtherPlayerDisconnected { seat: usize, forfeit_deadline: Option<u128> }, // Milliseconds since UNIX epoch, None if they can't forfeit
+    OtherPlayerReconnected { seat: usize },
+    OtherPlayerLeft { seat: usize }, // Closed the connection, they won't come back
+    WordRejected,
+    WordRejectedHardMode(HardModeViolation),
+    OtherPlayerWord { seat: usize, word: Option<String> }, // None if the player skipped their turn
+    WordHints(Vec<HintType>), // Empty if the player skipped their turn
+    EspionageResult { seat: usize, id: u64, hint: Option<HintType> }, // None if the spied player skipped their turn
+    AttackRejected(Attack),
+    GameOver(GameOver), // The only message telling that the game is over, and how
+    YouForfeited, // You didn't come back in time
+    OtherPlayerForfeited { seat: usize },
+    OtherPlayerResigned { seat: usize }, // Their teammate is out of the game too
+    Restart,
+    WaitForHost,
+    OtherPlayerIsDone { seat: usize },
+    TeamRoles { team: usize, turn: i64, typist: usize, captain: usize }, // Only in team mode, sent to the team at the start of each turn
+    TeammateSuggestion { seat: usize, choice: AttackChoice }, // Attack suggested to the captain by a teammate
+    MatchScore(MatchScore), // After each game of a series, also sent to spectators
+    MatchOver { winners: Vec<usize>, score: MatchScore }, // The series is decided, the next game starts a new one
+    PhaseDeadline { deadline: u128, turn: i64, phase: GamePhase }, // Milliseconds since UNIX epoch
+    TimerExpired,
+    GameOptions { options: GameOptions },
+    Error(ProtocolError), // The last message of the client was rejected
+    RoomExpired { reason: ExpiryReason }, // The room is deleted, the connection will be closed
+    SpectatorCount(usize), // Sent to the players and to the spectators
+    SpectatorState(SpectatorState), // Only sent to spectators, as the messages below
+    SpectatorWords { turn: i64, words: Vec<Option<String>> }, // One word per player, None if they skipped their turn
+    SpectatorHints { turn: i64, players: Vec<SpectatedTurn> },
+    SpectatorGameEnd { end: GameEnd, winners: Vec<usize>, player: Option<usize>, solution: String, hints: Vec<Vec<Vec<HintType>>> }, // Hints of each player, only revealed at the end
+}
+
+/// End of a game, from the point of view of the player who receives it
+#[derive(serde::Serialize)]
+pub struct GameOver {
+    pub outcome: GameOutcome,
+    pub winners: Vec<usize>, // Seats of the players who won, or who found the word in a draw. Empty if nobody won
+    pub player: Option<usize>, // Player whose resignation or abandonment ended the game
+    pub solution: String,
+    pub words: Vec<Option<String>>, // Words typed in the last turn, by seat. Not revealed if the game ended during a turn
+}
+
+#[derive(serde::Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum GameOutcome {
+    Win, // Your side found the word
+    Loss, // Another side found the word
+    DrawByBothSolving, // Your side and another one found the word in the same turn
+    DrawByExhaustion, // Nobody found the word before the last turn
+    Resignation, // All the other sides resigned, or yours did
+    Abandonment, // All the other players didn't come back in time, or you didn't
+}
+
+/// Everything a reconnecting player needs to rebuild the game
+#[derive(serde::Serialize)]
+pub struct StateResync {
+    pub seat: usize,
+    pub team: Option<usize>, // None without teams
+    pub game_started: bool,
+    pub current_turn: i64,
+    pub current_phase: GamePhase,
+    pub phase_deadline: Option<u128>, // Milliseconds since UNIX epoch, None if the timer is not running
+    pub options: GameOptions,
+    pub in_game: bool, // False if the player forfeited the current game
+    pub past_words: Vec<String>,
+    pub past_hints: Vec<Vec<HintType>>,
+    pub typed_word_this_turn: Option<String>,
+    pub attack_this_turn: Option<AttackChoice>,
+    pub other_players: Vec<OtherPlayerState>,
+    pub match_score: Option<MatchScore>, // None if the games are not played in series
+}
+
+/// Score of a series of games
+#[derive(serde::Serialize, Clone)]
+pub struct MatchScore {
+    pub format: MatchFormat,
+    pub games_played: u64,
+    pub wins: Vec<u64>, // For each seat
+}
+
+#[derive(serde::Serialize)]
+pub struct AttackChoice {
+    pub attack: Attack,
+    pub id: u64,
+    pub target: usize, // Seat of the attacked player
+}
+
+#[derive(serde::Serialize)]
+pub struct OtherPlayerState {
+    pub seat: usize,
+    pub team: Option<usize>,
+    pub info: Option<PlayerInfo>,
+    pub connected: bool,
+    pub forfeit_deadline: Option<u128>, // Same as in `other-player-disconnected`
+    pub in_game: bool, // False if they forfeited the current game
+    pub past_words: Vec<String>, // Already revealed to the player at the end of each typing phase
+    pub done_this_phase: bool, // Whether they already typed their word or attacked
+}
+
+/// What spectators know about the room, without what the players don't know yet
+#[derive(serde::Serialize)]
+pub struct SpectatorState {
+    pub game_started: bool,
+    pub current_turn: i64,
+    pub current_phase: GamePhase,
+    pub options: GameOptions,
+    pub players: Vec<SpectatedPlayer>, // Ordered by seat, the host first
+    pub match_score: Option<MatchScore>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SpectatedPlayer {
+    pub info: Option<PlayerInfo>,
+    pub connected: bool,
+    pub team: Option<usize>,
+    pub in_game: bool,
+    pub past_words: Vec<String>,
+    pub past_hints: Vec<Vec<HintType>>, // Empty during a game, the hints of a player would help the others
+}
+
+#[derive(serde::Serialize)]
+pub struct SpectatedTurn {
+    pub attack: Option<AttackChoice>, // Visible sabotage of the player, None for the attacks that the target doesn't see
+}
+
+/// Why a message of a client was rejected, sent back to the client in an `error` message
+#[derive(serde::Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorCode {
+    MalformedJson, // Not JSON at all
+    InvalidMessage, // Unknown type, or content that doesn't match the type
+    PayloadTooLarge,
+    BinaryMessage, // Messages must be sent in text frames
+    WrongPhase, // The action can't be done in the current phase of the game
+    ActionAlreadyDone, // The player already typed their word or attacked in this phase
+    InvalidLetterIndex, // The index of the attacked letter is not in the word
+    InvalidTarget, // The attacked player is not an opponent, or the target is missing with several opponents
+    NotYourTurn, // In team mode, the teammate of the player types this turn
+    InvalidOptions,
+    NotAPlayer, // Spectators can't act
+    InternalError, // Bug of the server, the state of the game is sent again with `state-resync`
+}
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct ProtocolError {
+    pub code: ErrorCode, // For the client to know what happened
+    pub message: String, // Explanation, for humans
+}
+
+impl ProtocolError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> ProtocolError {
+        ProtocolError { code, message: message.into() }
+    }
+}
+
+impl std::fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}: {}", self.code, self.message)
+    }
+}
+
+/// Maximum size of a message sent by a client, in bytes. The biggest messages (options) are way smaller
+pub const MAX_CLIENT_MESSAGE_SIZE: usize = 4096;
+
+pub fn decode_client_message(text: &str) -> Result<ClientMessage, ProtocolError> {
+    if text.len() > MAX_CLIENT_MESSAGE_SIZE {
+        return Err(ProtocolError::new(ErrorCode::PayloadTooLarge, format!("Messages can't be longer than {} bytes", MAX_CLIENT_MESSAGE_SIZE)));
+    }
+
+    let value = serde_json::from_str::<serde_json::Value>(text).map_err(|err| ProtocolError::new(ErrorCode::MalformedJson, err.to_string()))?;
+    serde_json::from_value(value).map_err(|err| ProtocolError::new(ErrorCode::InvalidMessage, err.to_string()))
+}
+
+pub fn encode_server_message(message: &ServerMessage) -> String {
+    serde_json::to_string(message).unwrap() // Can't fail, all the keys of the maps are strings or unit variants
+}
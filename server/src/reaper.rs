@@ -0,0 +1,119 @@
+use crate::{GamePhase, RoomState, server_internal, spectators, statistics};
+use crate::protocol::ServerMessage;
+use crate::room_actor::RoomCommand;
+
+use std::time::{Duration, Instant};
+
+/// Interval between two checks of all the rooms
+const REAPER_INTERVAL: u64 = 5000; // ms
+
+/// When rooms are deleted (see `config`). Durations are in seconds, 0 disables the rule
+#[derive(Clone, Copy, Debug)]
+pub struct ReaperPolicy {
+    pub empty_room_timeout: u64, // All players are disconnected
+    pub lobby_timeout: u64, // Nobody joined the host
+    pub abandoned_seat_timeout: u64, // The only player still there waits for disconnected players
+    pub max_room_age: u64,
+}
+
+/// Why a room was deleted, sent to the players still connected and counted in the statistics
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExpiryReason {
+    Empty, // All the players left, and no game was in progress
+    AbandonedDuringGame, // All the players left during a game
+    NeverJoined,
+    SeatAbandoned, // The other players were disconnected and didn't come back
+    TooOld,
+}
+
+/// Whether the rule is enabled and the duration since `since` is longer than the timeout
+fn is_expired(since: Instant, timeout: u64, now: Instant) -> bool {
+    timeout > 0 && now.saturating_duration_since(since) > Duration::from_secs(timeout)
+}
+
+/// Returns why the room must be deleted, None if it can be kept
+pub fn get_expiry_reason(room: &RoomState, policy: &ReaperPolicy) -> Option<ExpiryReason> {
+    let now = Instant::now();
+
+    if is_expired(room.created_at, policy.max_room_age, now) {
+        return Some(ExpiryReason::TooOld);
+    }
+
+    let disconnected_since: Vec<Instant> = room.players.iter().filter_map(|p| p.disconnected_since).collect();
+
+    if disconnected_since.len() == room.players.len() { // Nobody is connected
+        let last_disconnection = disconnected_since.into_iter().max().unwrap();
+
+        if is_expired(last_disconnection, policy.empty_room_timeout, now) {
+            let in_game = room.game_started && room.game_state.current_phase != GamePhase::Restarting;
+            return Some(if in_game { ExpiryReason::AbandonedDuringGame } else { ExpiryReason::Empty });
+        }
+
+        return None;
+    }
+
+    if room.players.len() == 1 && is_expired(room.created_at, policy.lobby_timeout, now) {
+        return Some(ExpiryReason::NeverJoined);
+    }
+
+    if is_seat_abandoned(room, policy, now) {
+        return Some(ExpiryReason::SeatAbandoned);
+    }
+
+    None
+}
+
+/// Whether the players still connected can't play anymore because the others left without closing their connection.
+/// Players who closed their connection or who are out of the current game don't block the others, they are ignored
+fn is_seat_abandoned(room: &RoomState, policy: &ReaperPolicy, now: Instant) -> bool {
+    let game_in_progress = crate::game::is_game_in_progress(room);
+    let seats: Vec<&crate::Player> = room.players.iter().filter(|p| !p.left && (p.in_game || !game_in_progress)).collect();
+
+    let connected_count = seats.iter().filter(|p| p.disconnected_since.is_none()).count();
+    let disconnected_since: Vec<Instant> = seats.iter().filter_map(|p| p.disconnected_since).collect();
+
+    // Expire only if at most one player is left to play, and all the others are gone for too long
+    connected_count <= 1 && !disconnected_since.is_empty()
+        && disconnected_since.into_iter().all(|since| is_expired(since, policy.abandoned_seat_timeout, now))
+}
+
+/// Tells the players still connected that the room is deleted, and ends their connections
+pub fn close_room(room: &mut RoomState, reason: ExpiryReason) {
+    server_internal::send_message_to_all_players(room, ServerMessage::RoomExpired { reason });
+    spectators::send_message_to_spectators(room, ServerMessage::RoomExpired { reason });
+    spectators::remove_all_spectators(room);
+
+    for player in room.players.iter_mut() {
+        server_internal::detach_connection(player);
+    }
+}
+
+/// Periodically checks all the rooms and deletes the ones that expired
+pub fn start_reaper(data: &'static crate::ProtectedAppState, policy: ReaperPolicy) {
+    actix_web::rt::spawn(async move { loop {
+        actix_web::rt::time::sleep(Duration::from_millis(REAPER_INTERVAL)).await;
+
+        let rooms: Vec<_> = crate::util::lock(&data.rooms).iter().map(|(code, room)| (code.clone(), room.clone())).collect();
+
+        for (code, room) in rooms {
+            let expiry = room.request(|reply| RoomCommand::CheckExpiry { policy, reply }).await;
+
+            let reason = match expiry {
+                Some(Some(reason)) => reason,
+                Some(None) => continue, // Room still used
+                None => { // The task of the room ended by itself
+                    crate::util::lock(&data.rooms).remove(&code);
+                    continue;
+                },
+            };
+
+            crate::util::lock(&data.rooms).remove(&code);
+            println!("Room {} deleted: {:?}", code, reason);
+
+            statistics::update_stats(&data.statistics, &|stats| {
+                statistics::increment_stat_map_counter(&mut stats.expired_rooms, reason);
+            });
+        }
+    }});
+}
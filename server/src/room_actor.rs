@@ -0,0 +1,310 @@
+use crate::{HOST_SEAT, Player, RoomState, game, reaper, room_views, server_internal, spectators};
+use crate::protocol::ServerMessage;
+
+use tokio::sync::{mpsc, oneshot};
+
+/// Channel used to send the messages to a connected player. Closed by the room to end the connection
+pub type Outbox = mpsc::UnboundedSender<String>;
+
+/// Who is at the other end of a connection
+#[derive(Clone, Copy)]
+pub enum ConnectionRole {
+    Player { seat: usize },
+    Spectator,
+}
+
+/// Everything that can happen to a room. Commands are handled one by one by the task of the room
+pub enum RoomCommand {
+    Join { outbox: Outbox, reply: oneshot::Sender<Result<(usize, u64), JoinError>> }, // Replies the seat and the connection id
+    Reconnect { seat: usize, token: String, outbox: Outbox, reply: oneshot::Sender<Result<u64, JoinError>> },
+    Spectate { outbox: Outbox, reply: oneshot::Sender<Result<u64, JoinError>> },
+    ClientMessage { role: ConnectionRole, connection_id: u64, text: String },
+    Disconnect { role: ConnectionRole, connection_id: u64, unsent: Vec<String>, left: bool }, // Messages that couldn't be sent on the connection. `left` if the player closed the connection
+    Heartbeat { role: ConnectionRole, connection_id: u64 }, // The connection is still alive
+    Tick, // One of the deadlines of the room was reached (see `get_next_wake_up`)
+    GetPublicView { reply: oneshot::Sender<room_views::PublicRoomView> },
+    GetAdminView { reply: oneshot::Sender<room_views::AdminRoomView> },
+    CheckExpiry { policy: reaper::ReaperPolicy, reply: oneshot::Sender<Option<reaper::ExpiryReason>> }, // The room stops if it expired
+}
+
+#[derive(Debug)]
+pub enum JoinError {
+    RoomNotFound,
+    RoomFull,
+    InvalidToken,
+    NotDisconnected,
+    PlayerLeft, // The player closed the connection, they can't reconnect
+    TooManySpectators,
+}
+
+/// What the rest of the server knows about a room: the room itself is only accessed by its task
+#[derive(Clone)]
+pub struct RoomHandle {
+    commands: mpsc::UnboundedSender<RoomCommand>,
+}
+
+impl RoomHandle {
+    /// Returns false if the room doesn't exist anymore
+    pub fn send(&self, command: RoomCommand) -> bool {
+        self.commands.send(command).is_ok()
+    }
+
+    /// Sends a command and waits for the reply. None if the room doesn't exist anymore
+    pub async fn request<T>(&self, command: impl FnOnce(oneshot::Sender<T>) -> RoomCommand) -> Option<T> {
+        let (reply, receiver) = oneshot::channel();
+
+        if !self.send(command(reply)) {
+            return None;
+        }
+
+        receiver.await.ok()
+    }
+}
+
+/// Starts the task of the room. The room is deleted when it expires (see `reaper`)
+pub fn start_room(mut room: RoomState) -> RoomHandle {
+    let (commands, mut receiver) = mpsc::unbounded_channel();
+    let handle = RoomHandle { commands };
+
+    actix_web::rt::spawn(async move { loop {
+        // The room only wakes up by itself when one of its deadlines is reached
+        let command = tokio::select! {
+            command = receiver.recv() => match command {
+                Some(command) => command,
+                None => break,
+            },
+            _ = sleep_until(get_next_wake_up(&room)) => RoomCommand::Tick,
+        };
+
+        if !handle_command(&mut room, command) {
+            break; // Dropping the room ends the connections
+        }
+    }});
+
+    handle
+}
+
+/// Earliest deadline of the room: end of the phase, forfeit of a disconnected player, or silent connection. None if there is nothing to wait for
+fn get_next_wake_up(room: &RoomState) -> Option<std::time::Instant> {
+    let forfeit_deadlines = (0..room.players.len()).filter_map(|seat| game::get_forfeit_deadline(room, seat));
+    let silence_deadlines = room.players.iter().filter(|p| p.connection_alive).map(|p| server_internal::get_silence_deadline(p.last_ping_time));
+
+    room.game_state.phase_deadline.into_iter()
+        .chain(forfeit_deadlines)
+        .chain(silence_deadlines)
+        .chain(spectators::get_next_silence_deadline(room))
+        .min()
+}
+
+/// Waits until the deadline, or forever without deadline
+async fn sleep_until(deadline: Option<std::time::Instant>) {
+    match deadline {
+        Some(deadline) => actix_web::rt::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Returns false if the room must be deleted
+fn handle_command(room: &mut RoomState, command: RoomCommand) -> bool {
+    match command {
+        RoomCommand::Join { outbox, reply } => {
+            let _ = reply.send(join(room, outbox));
+        },
+        RoomCommand::Reconnect { seat, token, outbox, reply } => {
+            let _ = reply.send(reconnect(room, seat, &token, outbox));
+        },
+        RoomCommand::Spectate { outbox, reply } => {
+            let _ = reply.send(spectators::add_spectator(room, outbox));
+        },
+        RoomCommand::ClientMessage { role: ConnectionRole::Spectator, connection_id, text } => {
+            spectators::handle_spectator_message(room, connection_id, &text);
+        },
+        RoomCommand::ClientMessage { role: ConnectionRole::Player { seat }, connection_id, text } => {
+            if !is_current_connection(room, seat, connection_id) {
+                return true; // Message from an old connection
+            }
+
+            room.get_player(seat).last_ping_time = std::time::Instant::now();
+
+            if let Err(msg) = server_internal::handle_one_message_internal(room, &text, seat) {
+                log::error!("{}", msg);
+            }
+        },
+        RoomCommand::Disconnect { role: ConnectionRole::Spectator, connection_id, .. } => {
+            spectators::remove_spectator(room, connection_id);
+        },
+        RoomCommand::Disconnect { role: ConnectionRole::Player { seat }, connection_id, unsent, left } => {
+            if !is_current_connection(room, seat, connection_id) {
+                return true;
+            }
+
+            room.get_player(seat).left = left;
+            disconnect_player(room, seat);
+            let player = room.get_player(seat);
+
+            // Messages that couldn't be sent were queued before the others
+            let mut messages = unsent;
+            messages.append(&mut player.messages_to_send);
+            player.messages_to_send = messages;
+
+            if left {
+                println!("A player left room {}", room.join_code);
+                server_internal::run_room_logic(room, game::check_for_forfeit); // No need to wait for them
+            }
+        },
+        RoomCommand::Heartbeat { role: ConnectionRole::Spectator, connection_id } => {
+            spectators::on_heartbeat(room, connection_id);
+        },
+        RoomCommand::Heartbeat { role: ConnectionRole::Player { seat }, connection_id } => {
+            if is_current_connection(room, seat, connection_id) {
+                room.get_player(seat).last_ping_time = std::time::Instant::now();
+            }
+        },
+        RoomCommand::Tick => {
+            server_internal::run_room_logic(room, game::check_for_phase_timeout);
+            server_internal::run_room_logic(room, game::check_for_forfeit);
+
+            for seat in 0..room.players.len() {
+                if is_silent(room.get_player(seat)) {
+                    disconnect_player(room, seat);
+                    println!("A player is disconnected because they sent no pings");
+                }
+            }
+
+            spectators::remove_silent_spectators(room);
+        },
+        RoomCommand::GetPublicView { reply } => {
+            let _ = reply.send(room_views::get_public_view(room));
+        },
+        RoomCommand::GetAdminView { reply } => {
+            let _ = reply.send(room_views::get_admin_view(room));
+        },
+        RoomCommand::CheckExpiry { policy, reply } => {
+            let reason = reaper::get_expiry_reason(room, &policy);
+            let _ = reply.send(reason);
+
+            if let Some(reason) = reason {
+                reaper::close_room(room, reason);
+                return false;
+            }
+        },
+    }
+
+    return true;
+}
+
+fn is_current_connection(room: &mut RoomState, seat: usize, connection_id: u64) -> bool {
+    room.player_exists(seat) && room.get_player(seat).connection_alive && room.get_player(seat).connection_id == connection_id
+}
+
+/// Marks the player as disconnected and tells the other players
+fn disconnect_player(room: &mut RoomState, seat: usize) {
+    server_internal::detach_connection(room.get_player(seat));
+    spectators::send_spectator_state(room);
+
+    let left = room.get_player(seat).left;
+    let forfeit_deadline = game::get_forfeit_deadline(room, seat).map(game::get_timestamp);
+
+    for other in (0..room.players.len()).filter(|other| *other != seat) {
+        let message = if left { ServerMessage::OtherPlayerLeft { seat } } else { ServerMessage::OtherPlayerDisconnected { seat, forfeit_deadline } };
+        server_internal::send_message(room.get_player(other), message);
+    }
+}
+
+/// Whether the player sent nothing (messages, pings or pongs) for too long
+fn is_silent(player: &Player) -> bool {
+    player.connection_alive && std::time::Instant::now() >= server_internal::get_silence_deadline(player.last_ping_time)
+}
+
+/// Whether a new player can take the seat: a disconnected player keeps their seat during the grace period,
+/// or until they leave if there is no grace period (wait forever)
+fn is_seat_free(player: &Player, grace_period: std::time::Duration) -> bool {
+    if player.connection_alive {
+        return false;
+    }
+
+    if player.left {
+        return true;
+    }
+
+    match player.disconnected_since {
+        Some(_) if grace_period.is_zero() => false,
+        Some(since) => since.elapsed() >= grace_period,
+        None => true,
+    }
+}
+
+/// Gives the new player the first free seat, or a new one if the room is not full
+fn join(room: &mut RoomState, outbox: Outbox) -> Result<(usize, u64), JoinError> {
+    if game::is_game_in_progress(room) { // Players can join before the first game or between two games, to replace those who left
+        return Err(JoinError::RoomFull);
+    }
+
+    let grace_period = std::time::Duration::from_secs(room.reconnect_grace_period);
+    let free_seat = (0..room.players.len()).find(|seat| *seat != HOST_SEAT && is_seat_free(&room.players[*seat], grace_period));
+
+    let seat = match free_seat {
+        Some(seat) => seat,
+        None if room.players.len() < room.game_options.max_players => room.players.len(),
+        None => return Err(JoinError::RoomFull),
+    };
+
+    println!("Player is joining room {} at seat {}", room.join_code, seat);
+
+    let mut player = Player::new(None);
+    player.ready_to_restart = true; // Player is immediately ready
+    let connection_id = server_internal::attach_connection(&mut player, outbox);
+
+    if seat == room.players.len() { // Add player to room
+        room.players.push(player);
+    }
+    else {
+        room.players[seat] = player;
+    }
+
+    game::send_session_token(room.get_player(seat));
+    server_internal::send_message(room.get_player(seat), ServerMessage::Seat(seat));
+
+    for other in (0..room.players.len()).filter(|other| *other != seat) { // Tell the other players
+        server_internal::send_message(room.get_player(other), ServerMessage::OtherPlayerConnected { seat });
+    }
+
+    game::send_other_players_info(room, seat); // Tell who is already there
+    spectators::send_spectator_state(room);
+
+    server_internal::run_room_logic(room, game::check_for_restart_end);
+
+    if !game::is_game_in_progress(room) {
+        server_internal::send_message(room.get_player(seat), ServerMessage::WaitForHost);
+    }
+
+    Ok((seat, connection_id))
+}
+
+fn reconnect(room: &mut RoomState, seat: usize, token: &str, outbox: Outbox) -> Result<u64, JoinError> {
+    if !room.player_exists(seat) || room.get_player(seat).session_token != token {
+        return Err(JoinError::InvalidToken);
+    }
+
+    if room.get_player(seat).connection_alive {
+        return Err(JoinError::NotDisconnected);
+    }
+
+    if room.get_player(seat).left {
+        return Err(JoinError::PlayerLeft);
+    }
+
+    let connection_id = server_internal::attach_connection(room.get_player(seat), outbox);
+
+    // The player may have lost the info of the other players
+    game::send_other_players_info(room, seat);
+    for other in (0..room.players.len()).filter(|other| *other != seat) {
+        server_internal::send_message(room.get_player(other), ServerMessage::OtherPlayerReconnected { seat });
+    }
+
+    spectators::send_spectator_state(room);
+
+    game::send_state_resync(room, seat);
+
+    Ok(connection_id)
+}
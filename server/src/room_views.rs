@@ -0,0 +1,102 @@
+use crate::{Attack, GameOptions, GamePhase, HintType, PlayerInfo, RoomState};
+
+/// What anybody knowing the room code can see. Must not contain anything that helps a player to cheat
+/// (no word to guess, no words or hints of the players).
+#[derive(serde::Serialize)]
+pub struct PublicRoomView {
+    join_code: String,
+    game_started: bool,
+    game_count: u64,
+    current_turn: i64,
+    current_phase: GamePhase,
+    options: GameOptions,
+    players: Vec<PublicPlayerView>,
+    spectator_count: usize,
+}
+
+#[derive(serde::Serialize)]
+pub struct PublicPlayerView {
+    seat: usize,
+    team: Option<usize>,
+    info: Option<PlayerInfo>,
+    connected: bool,
+    word_count: usize, // How many words the player typed in this game
+}
+
+/// Full state of the room, for debugging. Only for admins
+#[derive(serde::Serialize)]
+pub struct AdminRoomView {
+    join_code: String,
+    game_started: bool,
+    game_count: u64,
+    word_to_guess: Option<String>,
+    current_turn: i64,
+    current_phase: GamePhase,
+    phase_deadline_in_ms: Option<u128>, // Time before the deadline, including the grace period
+    options: GameOptions,
+    players: Vec<AdminPlayerView>,
+}
+
+#[derive(serde::Serialize)]
+pub struct AdminPlayerView {
+    seat: usize,
+    team: Option<usize>,
+    info: Option<PlayerInfo>,
+    connected: bool,
+    left: bool,
+    in_game: bool,
+    messages_to_send: usize,
+    typed_word_this_turn: Option<String>,
+    turn_skipped: bool,
+    attack_this_turn: Option<(Attack, u64, usize)>, // Attack, letter index, seat of the target
+    past_words: Vec<String>,
+    past_hints: Vec<Vec<HintType>>,
+    ready_to_restart: bool,
+}
+
+pub fn get_public_view(room: &RoomState) -> PublicRoomView {
+    PublicRoomView {
+        join_code: room.join_code.clone(),
+        game_started: room.game_started,
+        game_count: room.game_count,
+        current_turn: room.game_state.current_turn,
+        current_phase: room.game_state.current_phase,
+        options: room.game_options.clone(),
+        players: room.players.iter().enumerate().map(|(seat, p)| PublicPlayerView {
+            seat,
+            team: p.team,
+            info: p.player_info.clone(),
+            connected: p.connection_alive,
+            word_count: p.past_words.len(),
+        }).collect(),
+        spectator_count: room.spectators.len(),
+    }
+}
+
+pub fn get_admin_view(room: &RoomState) -> AdminRoomView {
+    AdminRoomView {
+        join_code: room.join_code.clone(),
+        game_started: room.game_started,
+        game_count: room.game_count,
+        word_to_guess: room.game_state.word_to_guess.clone(),
+        current_turn: room.game_state.current_turn,
+        current_phase: room.game_state.current_phase,
+        phase_deadline_in_ms: room.game_state.phase_deadline.map(|d| d.saturating_duration_since(std::time::Instant::now()).as_millis()),
+        options: room.game_options.clone(),
+        players: room.players.iter().enumerate().map(|(seat, p)| AdminPlayerView {
+            seat,
+            team: p.team,
+            info: p.player_info.clone(),
+            connected: p.connection_alive,
+            left: p.left,
+            in_game: p.in_game,
+            messages_to_send: p.messages_to_send.len(),
+            typed_word_this_turn: p.typed_word_this_turn.clone(),
+            turn_skipped: p.turn_skipped,
+            attack_this_turn: p.attack_this_turn.map(|a| (a.attack, a.letter_id, a.target)),
+            past_words: p.past_words.clone(),
+            past_hints: p.past_hints.clone(),
+            ready_to_restart: p.ready_to_restart,
+        }).collect(),
+    }
+}
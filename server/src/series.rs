@@ -0,0 +1,87 @@
+use crate::{MatchFormat, RoomState, server_internal, spectators, statistics};
+use crate::protocol::{self, ServerMessage};
+
+/// Highest number of games (best of) or wins (first to) that can be set in the options
+pub const MAX_SERIES_LENGTH: u64 = 9;
+
+// A series is a match of several games between the same players, see `MatchFormat`.
+// The wins are counted on the players (`Player::series_wins`), the series is over once a player
+// (or a team, as each teammate wins) reached the wins needed, and the next game starts a new series.
+
+/// Counts the result of a game. `winners` is empty for draws and abandoned games
+pub fn on_game_end(room: &mut RoomState, winners: &[usize]) {
+    if room.game_options.match_format == MatchFormat::Single {
+        return;
+    }
+
+    room.series_games += 1;
+    for &winner in winners {
+        room.get_player(winner).series_wins += 1;
+    }
+
+    let score = get_score(room);
+    spectators::send_message_to_spectators(room, ServerMessage::MatchScore(score.clone()));
+    server_internal::send_message_to_all_players(room, ServerMessage::MatchScore(score.clone()));
+
+    let Some(series_winners) = get_series_winners(room) else {
+        return; // Not decided yet
+    };
+
+    println!("Series finished in room {} after {} games", room.join_code, room.series_games);
+    room.series_over = true;
+
+    spectators::send_message_to_spectators(room, ServerMessage::MatchOver { winners: series_winners.clone(), score: score.clone() });
+    server_internal::send_message_to_all_players(room, ServerMessage::MatchOver { winners: series_winners, score });
+
+    statistics::update_stats(&room.statistics, &|stats| {
+        stats.finished_series += 1;
+        statistics::increment_stat_map_counter(&mut stats.series_length, room.series_games);
+    });
+}
+
+/// Starts a new series if the last one is over. Called when a game starts
+pub fn on_game_start(room: &mut RoomState) {
+    if room.series_over {
+        reset(room);
+    }
+}
+
+/// Forgets the current series, for instance when the format changes
+pub fn reset(room: &mut RoomState) {
+    room.series_games = 0;
+    room.series_over = false;
+    for player in room.players.iter_mut() {
+        player.series_wins = 0;
+    }
+}
+
+/// Score of the current series, None if the games are not played in series
+pub fn get_current_score(room: &RoomState) -> Option<protocol::MatchScore> {
+    match room.game_options.match_format {
+        MatchFormat::Single => None,
+        _ => Some(get_score(room)),
+    }
+}
+
+fn get_score(room: &RoomState) -> protocol::MatchScore {
+    protocol::MatchScore {
+        format: room.game_options.match_format,
+        games_played: room.series_games,
+        wins: room.players.iter().map(|p| p.series_wins).collect(),
+    }
+}
+
+/// Seats of the players who won the series, None if it is not decided yet. Several players can win together
+/// (teammates, or players with the same number of wins when all the games of a best of were played)
+fn get_series_winners(room: &RoomState) -> Option<Vec<usize>> {
+    let best = room.players.iter().map(|p| p.series_wins).max().unwrap_or(0);
+    let best_players: Vec<usize> = (0..room.players.len()).filter(|seat| room.players[*seat].series_wins == best).collect();
+
+    let decided = match room.game_options.match_format {
+        MatchFormat::Single => false,
+        MatchFormat::FirstTo(wins) => best >= wins,
+        MatchFormat::BestOf(games) => best > games / 2 || room.series_games >= games,
+    };
+
+    if decided { Some(best_players) } else { None }
+}
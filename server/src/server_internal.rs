@@ -1,37 +1,83 @@
-
 use crate::Player;
 use crate::RoomState;
 use crate::game;
+use crate::protocol::{self, ErrorCode, ProtocolError, ServerMessage};
+use crate::room_actor::{ConnectionRole, Outbox, RoomCommand, RoomHandle};
 
 use actix_web::HttpResponse;
 use actix_web::web;
 use futures_util::TryStreamExt;
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Used to give a different id to each connection
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Interval between two websocket pings sent by the server. Must be smaller than the player silence timeout
+pub const SERVER_PING_INTERVAL: u64 = 3000; // ms
+
+/// When a connection that sent nothing (messages, pings or pongs) since `last_ping_time` is considered silent
+pub fn get_silence_deadline(last_ping_time: std::time::Instant) -> std::time::Instant {
+    last_ping_time + std::time::Duration::from_secs(crate::config::get().player_silence_timeout)
+}
+
+pub fn send_message(player: &mut Player, message: ServerMessage) {
+    let message_text = protocol::encode_server_message(&message);
+    push_message(player, message_text);
+}
+
+pub fn send_message_to_all_players(room: &mut RoomState, message: ServerMessage) {
+    let message_text = protocol::encode_server_message(&message);
+
+    for player in room.players.iter_mut() {
+        push_message(player, message_text.clone());
+    }
+}
 
-const PLAYER_CONNECTION_LOOP_INTERVAL: u64 = 100; // ms
+/// Sends the message to the connection of the player.
+/// If the player is disconnected, the message is queued until they reconnect.
+fn push_message(player: &mut Player, message_text: String) {
+    let message_text = match &player.outbox {
+        Some(outbox) => match outbox.send(message_text) {
+            Ok(()) => return,
+            Err(err) => err.0, // The connection just ended, the room will soon be told
+        },
+        None => message_text,
+    };
 
-/// Maximum duration before a player is disconnected it they sent no pings
-const PLAYER_SILENCE_MAX_DURATION: u64 = 10000; // ms
+    player.outbox = None;
+    player.messages_to_send.push(message_text);
+}
 
+/// Uses this connection to send the messages to the player, starting with the queued messages. Returns the id of the connection
+pub fn attach_connection(player: &mut Player, outbox: Outbox) -> u64 {
+    player.outbox = Some(outbox);
+    player.connection_alive = true;
+    player.disconnected_since = None;
+    player.connection_id = next_connection_id();
+    player.last_ping_time = std::time::Instant::now();
+
+    for message_text in std::mem::take(&mut player.messages_to_send) {
+        push_message(player, message_text);
+    }
 
-pub fn create_message_text<T>(message_type: &str, message_contents: &T) -> String where T : serde::Serialize {
-    let serialized_contents = serde_json::to_string(message_contents).unwrap();
-    format!("{{\"type\":\"{}\",\"content\":{}}}", message_type, serialized_contents)
+    player.connection_id
 }
 
-pub fn send_message<T>(player: &mut Player, message_type: &str, message_contents: &T) where T : serde::Serialize {
-    let message_text = create_message_text(message_type, message_contents);
-    player.messages_to_send.push(message_text.clone());
+pub fn next_connection_id() -> u64 {
+    NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed)
 }
 
-pub fn send_message_to_both_players<T>(room: &mut RoomState, message_type: &str, message_contents: &T) where T : serde::Serialize {
-    let message_text = create_message_text(message_type, message_contents);
-    
-    room.host_player.messages_to_send.push(message_text.clone());
-    room.other_player.as_mut().map(|other_player| other_player.messages_to_send.push(message_text.clone()));
+/// Marks the player as disconnected. Dropping the outbox ends the connection if it is still open
+pub fn detach_connection(player: &mut Player) {
+    if player.connection_alive {
+        player.disconnected_since = Some(std::time::Instant::now());
+    }
+
+    player.connection_alive = false;
+    player.outbox = None;
 }
 
-pub fn start_websocket(req: actix_web::HttpRequest, stream: web::Payload) 
+pub fn start_websocket(req: actix_web::HttpRequest, stream: web::Payload)
     -> Result<(HttpResponse, crate::SocketConnection), actix_web::Error> {
     let (res, session, stream) = actix_ws::handle(&req, stream)?;
 
@@ -42,111 +88,128 @@ pub fn start_websocket(req: actix_web::HttpRequest, stream: web::Payload)
     Ok((res, crate::SocketConnection { session, stream }))
 }
 
-pub async fn handle_player_connection(room: Arc<Mutex<RoomState>>, host_player: bool, mut connection: crate::SocketConnection) -> Result<(), actix_ws::Closed> {
-    let cloned_arc = Arc::clone(&room);
-
-    // Connection loop (send messages, check for pings...)
-    actix_web::rt::spawn(async move { loop {
-        let mut messages_to_send = Vec::new();
-
-        { // Block where the room is locked
-            let mut room_ref = cloned_arc.lock().unwrap();
-            let player = room_ref.get_player(host_player);
-            
-            if !player.connection_alive { // End loop if disconnected
-                break;
-            }
-
-            if std::time::Instant::now().duration_since(player.last_ping_time).as_millis() > PLAYER_SILENCE_MAX_DURATION as u128 { // Disconnect if no pings
-                player.connection_alive = false;
-                let _ = connection.session.close(None).await;
-                println!("A player is disconnected because they sent no pings");
-                break;
-            }
-
-            // Get the list of messages to send from the room
-            std::mem::swap(&mut messages_to_send, &mut room_ref.get_player(host_player).messages_to_send);
-        }
-
-        // Send the messages
-        let mut all_ok = true;
-        messages_to_send.reverse();
-        while messages_to_send.len() > 0 {
-            let top = messages_to_send.pop().unwrap();
-            let sent_res = connection.session.text(top).await;
-            if sent_res.is_err() {
-                cloned_arc.lock().unwrap().get_player(host_player).connection_alive = false;
-                all_ok = false;
-                break; // Stop if connection closed
+/// Handles the websocket of a player or a spectator until it is closed, or until the room ends the connection by dropping the outbox.
+/// Messages received are sent to the room, messages of the outbox are sent to the client.
+/// The server pings the client regularly, and any frame received from the client proves that the connection is alive.
+pub fn handle_connection(room: RoomHandle, role: ConnectionRole, connection_id: u64, mut connection: crate::SocketConnection, mut outbox: tokio::sync::mpsc::UnboundedReceiver<String>) {
+    actix_web::rt::spawn(async move {
+        let mut unsent = Vec::new();
+        let mut left = false; // The client closed the connection on purpose
+        let mut ping_interval = actix_web::rt::time::interval(std::time::Duration::from_millis(SERVER_PING_INTERVAL));
+
+        loop {
+            tokio::select! {
+                message = outbox.recv() => match message {
+                    Some(text) => {
+                        if connection.session.text(text.clone()).await.is_err() {
+                            unsent.push(text);
+                            break; // Connection closed
+                        }
+                    },
+                    None => { // Disconnected by the room
+                        let _ = connection.session.close(None).await;
+                        return;
+                    },
+                },
+                _ = ping_interval.tick() => {
+                    if connection.session.ping(b"").await.is_err() {
+                        break;
+                    }
+                },
+                frame = connection.stream.try_next() => match frame {
+                    Ok(Some(actix_ws::AggregatedMessage::Text(text))) => {
+                        if !room.send(RoomCommand::ClientMessage { role, connection_id, text: text.to_string() }) {
+                            return; // Room deleted
+                        }
+                    },
+                    Ok(Some(actix_ws::AggregatedMessage::Ping(bytes))) => {
+                        if connection.session.pong(&bytes).await.is_err() {
+                            break;
+                        }
+                        room.send(RoomCommand::Heartbeat { role, connection_id });
+                    },
+                    Ok(Some(actix_ws::AggregatedMessage::Pong(_))) => {
+                        room.send(RoomCommand::Heartbeat { role, connection_id });
+                    },
+                    Ok(Some(actix_ws::AggregatedMessage::Binary(_))) => {
+                        let err = ProtocolError::new(ErrorCode::BinaryMessage, "Messages must be sent as text");
+                        if connection.session.text(protocol::encode_server_message(&ServerMessage::Error(err))).await.is_err() {
+                            break;
+                        }
+                    },
+                    Ok(Some(actix_ws::AggregatedMessage::Close(reason))) => { // The player left
+                        let _ = connection.session.close(reason).await;
+                        left = true;
+                        break;
+                    },
+                    Ok(None) | Err(_) => break, // Connection lost, the player can reconnect
+                },
             }
         }
 
-        if !all_ok { // If some messages couldn't be sent correctly, put them back in the list
-            messages_to_send.reverse();
-            let mut room_ref = cloned_arc.lock().unwrap();
-            std::mem::swap(&mut messages_to_send, &mut room_ref.get_player(host_player).messages_to_send);
-            break;
+        // Give back the messages that were not sent, so that they are sent after a reconnection
+        outbox.close();
+        while let Ok(text) = outbox.try_recv() {
+            unsent.push(text);
         }
 
-        actix_web::rt::time::sleep(std::time::Duration::from_millis(PLAYER_CONNECTION_LOOP_INTERVAL)).await;
-    }});
+        room.send(RoomCommand::Disconnect { role, connection_id, unsent, left });
+    });
+}
 
-    let room_ref = Arc::clone(&room);
+/// Checks the `X-Admin-Token` header against the admin token of the server
+pub fn is_admin_request(req: &actix_web::HttpRequest) -> bool {
+    match (&crate::config::get().admin_token, req.headers().get("X-Admin-Token")) {
+        (Some(token), Some(header)) => header.as_bytes() == token.as_bytes(),
+        _ => false,
+    }
+}
 
-    // Listen for messages
-    actix_web::rt::spawn(async move { loop {
-        if !room_ref.lock().unwrap().get_player(host_player).connection_alive {
-            break; // End loop if disconnected
-        }
+/// Reloads the dictionaries each time the process receives SIGHUP
+#[cfg(unix)]
+pub fn reload_dictionaries_on_sighup(dictionaries: crate::dictionary::DictionariesHandle) -> std::io::Result<()> {
+    let mut signal = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
 
-        match connection.stream.try_next().await {
-            Ok(Some(actix_ws::AggregatedMessage::Text(text))) => {
-                room.lock().unwrap().get_player(host_player).last_ping_time = std::time::Instant::now();
-
-                match handle_one_message_internal(Arc::clone(&room_ref), &text, host_player) {
-                    Ok(()) => {},
-                    Err(msg) => { log::error!("{}", msg); }
-                }
-            },
-            Ok(Some(actix_ws::AggregatedMessage::Ping(_))) => {
-                // TODO
-            },
-            _ => {
-                room.lock().unwrap().get_player(host_player).connection_alive = false;
-                break;
+    actix_web::rt::spawn(async move {
+        while signal.recv().await.is_some() {
+            match crate::dictionary::reload(&dictionaries) {
+                Ok(()) => println!("Dictionaries reloaded"),
+                Err(err) => log::error!("Couldn't reload dictionaries: {}", err),
             }
         }
-    }});
+    });
 
     Ok(())
 }
 
-fn handle_one_message_internal(room: Arc<Mutex<RoomState>>, text: &str, is_host: bool) -> Result<(), String> {
-    match serde_json::de::from_str::<serde_json::Value>(text).map_err(|err| err.to_string())? {
-        serde_json::Value::Object(o) => {
-            let msg_type = crate::util::get_json_str(&o, "type")?;
-            let msg_content = crate::util::get_json_obj(&o, "content")?;
+/// Handles a message of a client. If the message is rejected, the client receives an `error` message
+pub fn handle_one_message_internal(room: &mut RoomState, text: &str, seat: usize) -> Result<(), ProtocolError> {
+    let result = protocol::decode_client_message(text)
+        .and_then(|message| {
+            run_room_logic(room, |room| game::handle_one_message(room, message, seat))
+                .unwrap_or_else(|| Err(ProtocolError::new(ErrorCode::InternalError, "The server couldn't handle this message")))
+        });
 
-            game::handle_one_message(&mut room.lock().unwrap(), msg_type, msg_content, is_host)
-        },
-        _ => {
-            Err(format!("Websocket message is not an object"))
-        }
+    if let Err(err) = &result {
+        send_message(room.get_player(seat), ServerMessage::Error(err.clone()));
     }
 
+    result
 }
 
-pub fn remove_empty_rooms(rooms: &mut std::collections::HashMap<String, Arc<Mutex<RoomState>>>) {
-    rooms.retain(|_, room| {
-        match room.lock() {
-            Ok(room_ref) => {
-                // Room is still alive is at least one player have a valid connection
-                room_ref.host_player.connection_alive || (room_ref.other_player.is_some() && room_ref.other_player.as_ref().unwrap().connection_alive)
-            },
-            Err(_) => {
-                // The mutex is probably poisoned, so just remove the room and act as if nothing happened
-                false
-            },
-        }
-    });
+/// Runs game logic on the room. If it panics, the error is contained to this room:
+/// the room stays usable and the players receive the state of the game to stay in sync. Returns None in case of panic.
+pub fn run_room_logic<R>(room: &mut RoomState, logic: impl FnOnce(&mut RoomState) -> R) -> Option<R> {
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| logic(room))) {
+        Ok(res) => Some(res),
+        Err(_) => {
+            log::error!("Game logic panicked in room {}", room.join_code);
+
+            for seat in 0..room.players.len() {
+                game::send_state_resync(room, seat);
+            }
+
+            None
+        },
+    }
 }
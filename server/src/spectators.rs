@@ -0,0 +1,159 @@
+use crate::{Attack, RoomState, server_internal};
+use crate::game::GameEnd;
+use crate::protocol::{self, ErrorCode, ProtocolError, ServerMessage};
+use crate::room_actor::{JoinError, Outbox};
+
+/// Someone watching a room. Spectators receive what happens in the game once it is revealed to all the players,
+/// never the word to guess before the end or the words being typed. They can't act, and can't reconnect.
+pub struct Spectator {
+    outbox: Outbox,
+    connection_id: u64,
+    last_ping_time: std::time::Instant,
+}
+
+pub fn add_spectator(room: &mut RoomState, outbox: Outbox) -> Result<u64, JoinError> {
+    if room.spectators.len() >= crate::config::get().max_spectators {
+        return Err(JoinError::TooManySpectators);
+    }
+
+    println!("Spectator is joining room {}", room.join_code);
+
+    let connection_id = server_internal::next_connection_id();
+    room.spectators.push(Spectator { outbox, connection_id, last_ping_time: std::time::Instant::now() });
+
+    send_spectator_state(room);
+    send_spectator_count(room);
+
+    Ok(connection_id)
+}
+
+/// Dropping the outbox ends the connection if it is still open
+pub fn remove_spectator(room: &mut RoomState, connection_id: u64) {
+    let count = room.spectators.len();
+    room.spectators.retain(|s| s.connection_id != connection_id);
+
+    if room.spectators.len() != count {
+        send_spectator_count(room);
+    }
+}
+
+/// Removes the spectators that sent nothing (messages, pings or pongs) for too long
+pub fn remove_silent_spectators(room: &mut RoomState) {
+    let now = std::time::Instant::now();
+    let count = room.spectators.len();
+    room.spectators.retain(|s| now < server_internal::get_silence_deadline(s.last_ping_time));
+
+    if room.spectators.len() != count {
+        send_spectator_count(room);
+    }
+}
+
+/// When the first silent spectator must be removed, if they don't send anything until then
+pub fn get_next_silence_deadline(room: &RoomState) -> Option<std::time::Instant> {
+    room.spectators.iter().map(|s| server_internal::get_silence_deadline(s.last_ping_time)).min()
+}
+
+/// Removes all the spectators, ending their connections
+pub fn remove_all_spectators(room: &mut RoomState) {
+    room.spectators.clear();
+}
+
+pub fn on_heartbeat(room: &mut RoomState, connection_id: u64) {
+    if let Some(spectator) = room.spectators.iter_mut().find(|s| s.connection_id == connection_id) {
+        spectator.last_ping_time = std::time::Instant::now();
+    }
+}
+
+/// Spectators can only send pings, anything else is answered with an error
+pub fn handle_spectator_message(room: &mut RoomState, connection_id: u64, text: &str) {
+    on_heartbeat(room, connection_id);
+
+    let result = protocol::decode_client_message(text).and_then(|message| match message {
+        protocol::ClientMessage::Ping {} => Ok(()),
+        _ => Err(ProtocolError::new(ErrorCode::NotAPlayer, "Spectators can't act")),
+    });
+
+    if let Err(err) = result {
+        let message_text = protocol::encode_server_message(&ServerMessage::Error(err));
+        if let Some(spectator) = room.spectators.iter().find(|s| s.connection_id == connection_id) {
+            let _ = spectator.outbox.send(message_text);
+        }
+    }
+}
+
+pub fn send_message_to_spectators(room: &RoomState, message: ServerMessage) {
+    if room.spectators.is_empty() {
+        return;
+    }
+
+    let message_text = protocol::encode_server_message(&message);
+    for spectator in &room.spectators {
+        let _ = spectator.outbox.send(message_text.clone()); // If the connection ended, the room will soon be told
+    }
+}
+
+/// Tells everybody in the room how many spectators are watching
+pub fn send_spectator_count(room: &mut RoomState) {
+    let count = room.spectators.len();
+    send_message_to_spectators(room, ServerMessage::SpectatorCount(count));
+    server_internal::send_message_to_all_players(room, ServerMessage::SpectatorCount(count));
+}
+
+/// Sends everything spectators need to show the room. Sent again when the players or the game change
+pub fn send_spectator_state(room: &mut RoomState) {
+    if room.spectators.is_empty() {
+        return;
+    }
+
+    let game_in_progress = crate::game::is_game_in_progress(room);
+    let players = room.players.iter()
+        .map(|p| protocol::SpectatedPlayer {
+            info: p.player_info.clone(),
+            connected: p.connection_alive,
+            team: p.team,
+            in_game: p.in_game,
+            past_words: p.past_words.clone(),
+            past_hints: if game_in_progress { Vec::new() } else { p.past_hints.clone() },
+        })
+        .collect();
+
+    let state = protocol::SpectatorState {
+        game_started: room.game_started,
+        current_turn: room.game_state.current_turn,
+        current_phase: room.game_state.current_phase,
+        options: room.game_options.clone(),
+        players,
+        match_score: crate::series::get_current_score(room),
+    };
+
+    send_message_to_spectators(room, ServerMessage::SpectatorState(state));
+}
+
+/// Sends the words typed in this turn, once all the players know them
+pub fn send_spectator_words(room: &mut RoomState) {
+    let words = room.players.iter().map(|p| p.typed_word_this_turn.clone()).collect();
+
+    send_message_to_spectators(room, ServerMessage::SpectatorWords { turn: room.game_state.current_turn, words });
+}
+
+/// Sends the attacks of the players once the sabotage phase is over. Spectating must not help a player,
+/// so only the sabotages seen by their target are sent: the hints (where the invisible sabotages are) wait for the end of the game,
+/// and espionages are never sent
+pub fn send_spectator_hints(room: &mut RoomState) {
+    let players = room.players.iter()
+        .map(|p| protocol::SpectatedTurn {
+            attack: p.attack_this_turn
+                .filter(|a| a.attack == Attack::Sabotage)
+                .map(|a| protocol::AttackChoice { attack: a.attack, id: a.letter_id, target: a.target }),
+        })
+        .collect();
+
+    send_message_to_spectators(room, ServerMessage::SpectatorHints { turn: room.game_state.current_turn, players });
+}
+
+/// Players are designated by their seat
+pub fn send_spectator_game_end(room: &mut RoomState, end: GameEnd, winners: Vec<usize>, player: Option<usize>) {
+    let solution = room.game_state.word_to_guess.clone().unwrap_or_default();
+    let hints = room.players.iter().map(|p| p.past_hints.clone()).collect();
+    send_message_to_spectators(room, ServerMessage::SpectatorGameEnd { end, winners, player, solution, hints });
+}
@@ -11,28 +11,25 @@ static FILE_MUTEX: Option<Mutex<Stats>> = None;
 pub struct Stats {
     #[serde(default = "zero")]          pub total_wins: u64,
     #[serde(default = "zero")]          pub total_draws: u64,
+    #[serde(default = "zero")]          pub abandoned_games: u64, // A player didn't come back after a disconnection. Not counted in the other stats
+    #[serde(default = "empty_map")]     pub game_ends: HashMap<crate::game::GameEnd, u64>, // How games ended, resignations included
     #[serde(default = "empty_map")]     pub language: HashMap<crate::Language, u64>,
     #[serde(default = "empty_map")]     pub timer: HashMap<u64, u64>,
     #[serde(default = "empty_map")]     pub win_turn: HashMap<u64, u64>, // At which turn did the game end?
     #[serde(default = "empty_map")]     pub game_count_for_one_room: HashMap<u64, u64>, // How many games did people play in one room (accumulated, if played 3 games it counts for 1, 2 and 3 games)
     #[serde(default = "zero")]          pub max_room_active_at_same_time: u64,
+    #[serde(default = "zero")]          pub finished_series: u64,
+    #[serde(default = "empty_map")]     pub series_length: HashMap<u64, u64>, // How many games were played in finished series
+    #[serde(default = "empty_map")]     pub expired_rooms: HashMap<crate::reaper::ExpiryReason, u64>, // Why rooms were deleted
 }
 
 pub type StatsHandle = Arc<Mutex<Option<Stats>>>;
 
-pub fn update_stats(stats: &Arc<Mutex<Option<Stats>>>, update_fn: &dyn Fn(&mut Stats) -> ()) {
-    match stats.lock() {
-        Ok(mut stats) => {
-            match &mut *stats {
-                Some(st) => {
-                    update_fn(st);
-                    let _ = save(st);
-                },
-                None => (),
-            }
-        },
-        Err(_) => log::error!("Couldn't acquire stats mutex!")
-    }    
+pub fn update_stats(stats: &Arc<Mutex<Option<Stats>>>, update_fn: &dyn Fn(&mut Stats)) {
+    if let Some(st) = &mut *crate::util::lock(stats) {
+        update_fn(st);
+        let _ = save(st);
+    }
 }
 
 pub fn load() -> Option<Stats> {
@@ -52,11 +49,16 @@ pub fn load() -> Option<Stats> {
         return Some(Stats {
             total_draws: 0,
             total_wins: 0,
+            abandoned_games: 0,
+            game_ends: HashMap::new(),
             language: HashMap::new(),
             timer: HashMap::new(),
             win_turn: HashMap::new(),
             game_count_for_one_room: HashMap::new(),
             max_room_active_at_same_time: 0,
+            finished_series: 0,
+            series_length: HashMap::new(),
+            expired_rooms: HashMap::new(),
         });
     }
 }
@@ -89,9 +91,5 @@ fn zero() -> u64 {
 }
 
 fn get_stats_path() -> Result<String, String> {
-    Ok(String::from(
-        std::env::current_exe().map_err(|e| e.to_string())?
-        .parent().ok_or("couldn't get parent of executable")?
-        .join("statistics.json")
-        .to_str().ok_or("couldn't convert the stats path to string")?))
+    Ok(String::from(crate::config::get().stats_path.to_str().ok_or("couldn't convert the stats path to string")?))
 }
@@ -0,0 +1,108 @@
+use crate::{RoomState, server_internal};
+use crate::protocol::ServerMessage;
+
+/// Number of players in each team, and number of teams
+pub const TEAM_SIZE: usize = 2;
+pub const TEAM_COUNT: usize = 2;
+
+// In team mode, teammates share a board: they type one after the other (the typist of the turn),
+// and the other one (the captain) chooses the attack of the team. The board of the team is copied
+// in the `past_words` and `past_hints` of each teammate, so that everything else works as for one player.
+// Without teams, each player is alone on their side: they always type and always choose their attack.
+
+/// Teams are given at the start of each game, in the order of the seats of the players in the game:
+/// the first and third players against the second and fourth. Empty seats don't unbalance the teams
+pub fn assign_teams(room: &mut RoomState) {
+    let teams_enabled = room.game_options.teams;
+    let mut position = 0;
+
+    for player in room.players.iter_mut() {
+        player.team = None;
+
+        if teams_enabled && player.in_game {
+            player.team = Some(position % TEAM_COUNT);
+            position += 1;
+        }
+    }
+}
+
+/// Whether that many players make full teams, with exactly `TEAM_SIZE` members in each of them
+pub fn are_teams_full(player_count: usize) -> bool {
+    player_count == TEAM_SIZE * TEAM_COUNT
+}
+
+/// Seats of the members of the team who are still in the game
+pub fn get_team_members(room: &RoomState, team: usize) -> Vec<usize> {
+    (0..room.players.len()).filter(|seat| room.players[*seat].in_game && room.players[*seat].team == Some(team)).collect()
+}
+
+/// Whether the player must type a word this turn
+pub fn is_typist(room: &RoomState, seat: usize) -> bool {
+    let player = &room.players[seat];
+
+    match player.team {
+        _ if !player.in_game => false,
+        None => true,
+        Some(team) => {
+            let members = get_team_members(room, team);
+            members[room.game_state.current_turn.max(0) as usize % members.len()] == seat
+        },
+    }
+}
+
+/// Whether the player chooses the attack of their side this turn. The captain is a teammate who doesn't type, if there is one
+pub fn is_captain(room: &RoomState, seat: usize) -> bool {
+    let player = &room.players[seat];
+
+    match player.team {
+        _ if !player.in_game => false,
+        None => true,
+        Some(team) => {
+            let members = get_team_members(room, team);
+            let captain = members.iter().copied().find(|member| !is_typist(room, *member)).unwrap_or(members[0]);
+            captain == seat
+        },
+    }
+}
+
+/// Whether both players are on the same side (a player is on their own side)
+pub fn are_teammates(room: &RoomState, a: usize, b: usize) -> bool {
+    match (room.players[a].team, room.players[b].team) {
+        (Some(team_a), Some(team_b)) => team_a == team_b,
+        _ => a == b,
+    }
+}
+
+/// Seats of the players sharing the board of the player (including them)
+pub fn get_board_members(room: &RoomState, seat: usize) -> Vec<usize> {
+    (0..room.players.len()).filter(|other| room.players[*other].in_game && are_teammates(room, seat, *other)).collect()
+}
+
+/// Players who can be attacked by the player: the typists of the other sides
+pub fn get_opponents(room: &RoomState, seat: usize) -> Vec<usize> {
+    (0..room.players.len()).filter(|other| is_typist(room, *other) && !are_teammates(room, seat, *other)).collect()
+}
+
+/// Number of sides (teams, or players without teams) still in the game
+pub fn get_side_count(room: &RoomState) -> usize {
+    let seats: Vec<usize> = (0..room.players.len()).filter(|seat| room.players[*seat].in_game).collect();
+    seats.iter().filter(|seat| seats.iter().find(|other| are_teammates(room, **seat, **other)) == Some(*seat)).count()
+}
+
+/// Tells the members of each team who types and who attacks this turn
+pub fn send_team_roles(room: &mut RoomState) {
+    for team in 0..TEAM_COUNT {
+        let members = get_team_members(room, team);
+        let Some(&typist) = members.iter().find(|seat| is_typist(room, **seat)) else {
+            continue;
+        };
+        let Some(&captain) = members.iter().find(|seat| is_captain(room, **seat)) else {
+            continue;
+        };
+
+        for &member in &members {
+            let message = ServerMessage::TeamRoles { team, turn: room.game_state.current_turn, typist, captain };
+            server_internal::send_message(room.get_player(member), message);
+        }
+    }
+}
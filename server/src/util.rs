@@ -13,10 +13,10 @@ impl Position {
     }
 
     pub fn get_json(&self) -> String {
-        format!("[{},{}]", self.x.to_string(), self.y.to_string())
+        format!("[{},{}]", self.x, self.y)
     }
 
-    pub fn from_json_array(arr: &Vec<serde_json::Value>) -> Option<Position> {
+    pub fn from_json_array(arr: &[serde_json::Value]) -> Option<Position> {
         if arr.len() != 2 { return None }
 
         Some(Position { x: arr[0].as_f64()? as f32, y: arr[1].as_f64()? as f32 })
@@ -24,105 +24,27 @@ impl Position {
 
 }
 
-pub fn create_random_code() -> String {
-    return format!(
-        "{}-{}", 
-        get_random_secret_word(crate::Language::English), 
-        get_random_secret_word(crate::Language::English)
-    ).to_ascii_lowercase();
-}
-
-pub fn get_random_secret_word(lang: crate::Language) -> String {
-    let n = crate::game::WORD_LENGTH as usize;
-    let bytes: &[u8] = match lang {
-        crate::Language::English => include_bytes!("../../words/english-few.txt"),
-        crate::Language::French => include_bytes!("../../words/francais-few.txt"),
-    };
-
-    let word_count = bytes.len() / (n+1);
-    let rand_id = rand::random_range(0..word_count);
-    
-    let mut res = String::with_capacity(n);
-    for i in 0..n {
-        res.push(bytes[(n+1) * rand_id + i] as char);
-    }
-    res.to_uppercase()
-}
-
-// Should be in O(log(nb_words))
-pub fn is_valid_word(w: &str, lang: crate::Language) -> bool {
-    let n = crate::game::WORD_LENGTH as usize;
-
-    if !w.is_ascii() || w.chars().count() != n { return false; }
-
-    let bytes: &[u8] = match lang {
-        crate::Language::English => include_bytes!("../../words/english-all.txt"),
-        crate::Language::French => include_bytes!("../../words/francais-all.txt"),
-    };
-        
-    let w_lower = w.to_lowercase();
-    let w_bytes = w_lower.bytes();
-
-    enum Cmp { Less, Greater, Equal }
-    fn compare(word_id: usize, w_bytes: &std::str::Bytes, all_bytes: &[u8]) -> Cmp {
-        let n = crate::game::WORD_LENGTH as usize;
-        
-        for (i, b) in w_bytes.clone().enumerate() {
-            let ref_b = all_bytes[(n+1) * word_id + i];
-            if b < ref_b { return Cmp::Less; }
-            else if b > ref_b { return Cmp::Greater; }
-        }
-
-        return Cmp::Equal;
-    }
+pub fn create_random_code(dictionaries: &crate::dictionary::Dictionaries) -> String {
+    let n = crate::game::DEFAULT_WORD_LENGTH;
+    let random_word = || dictionaries.get_random_secret_word(&crate::Language::default_language(), n).unwrap_or_default();
 
-    let mut min = 0;
-    let mut max = bytes.len() / (n+1);
-
-    while max - min > 0 {
-        let mid = (min + max) / 2;
-
-        let cmp = compare(mid, &w_bytes, bytes);
-        
-        match cmp {
-            Cmp::Less => {
-                max = mid;
-            },
-            Cmp::Greater => {
-                min = mid + 1;
-            },
-            Cmp::Equal => return true
-        }
-    }
-
-    return false;
+    return format!("{}-{}", random_word(), random_word()).to_ascii_lowercase();
 }
 
-pub fn get_json_obj<'a>(o: &'a serde_json::Map<String, serde_json::Value>, field: &str) -> Result<&'a serde_json::Map<String, serde_json::Value>, String> {
-    match o.get(field).ok_or("No type field")? {
-        serde_json::Value::Object(map) => Ok(map),
-        _ => Err(format!("Wrong type, expected object for field {}", field))
-    }
-}
+/// Length of the tokens used to reconnect to a room
+const SESSION_TOKEN_SIZE: usize = 32;
 
-pub fn get_json_str<'a>(o: &'a serde_json::Map<String, serde_json::Value>, field: &str) -> Result<&'a str, String> {
-    match o.get(field).ok_or("No type field")? {
-        serde_json::Value::String(s) => Ok(s),
-        _ => Err(format!("Wrong type, expected string for field {}", field))
-    }
+/// Creates a random token that can't be guessed (the generator is cryptographically secure)
+pub fn create_session_token() -> String {
+    use rand::Rng;
+    rand::rng().sample_iter(&rand::distr::Alphanumeric)
+        .take(SESSION_TOKEN_SIZE)
+        .map(char::from)
+        .collect()
 }
 
-pub fn get_json_arr<'a>(o: &'a serde_json::Map<String, serde_json::Value>, field: &str) -> Result<&'a Vec<serde_json::Value>, String> {
-    match o.get(field).ok_or("No type field")? {
-        serde_json::Value::Array(vec) => Ok(vec),
-        _ => Err(format!("Wrong type, expected array for field {}", field))
-    }
+/// Locks the mutex, even if a thread panicked while holding it. 
+/// Game logic runs with `server_internal::run_room_logic`, so a panic doesn't leave a room unusable.
+pub fn lock<T>(mutex: &std::sync::Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
 }
-
-
-pub fn get_json_number<'a>(o: &'a serde_json::Map<String, serde_json::Value>, field: &str) -> Result<&'a serde_json::Number, String> {
-    match o.get(field).ok_or("No type field")? {
-        serde_json::Value::Number(n) => Ok(n),
-        _ => Err(format!("Wrong type, expected number for field {}", field))
-    }
-}
\ No newline at end of file